@@ -23,7 +23,7 @@ async fn main() -> Result<(), Box<dyn Error>> {
             }
             // return true need disconnect,false not disconnect
             // if true and the current state is disconnected, it will be ignored.
-            Ok(true)
+            Ok::<_, Box<dyn Error + Send + Sync>>(true)
         },
         (),
     )
@@ -28,7 +28,7 @@ async fn main() -> Result<()> {
             stream.read_exact(&mut buf).await?;
             assert_eq!(&buf, b"200\r\n");
             tx.send(()).map_err(|_| anyhow!("rx is close"))?;
-            Ok(true)
+            Ok::<_, anyhow::Error>(true)
         },
         tx,
     )
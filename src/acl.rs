@@ -0,0 +1,236 @@
+use crate::error::{Error, Result};
+use std::fmt;
+use std::net::IpAddr;
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::{Arc, RwLock};
+
+/// 访问规则动作
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum AclAction {
+    Allow,
+    Deny,
+}
+
+/// IPv4/IPv6 地址段,例如 10.0.0.0/8 或 ::1/128
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct IpCidr {
+    addr: IpAddr,
+    prefix: u8,
+}
+
+impl IpCidr {
+    /// 创建地址段,prefix 超出地址长度时返回 None,
+    /// ::ffff:a.b.c.d/96 以上的 IPv4 映射地址段转换成对应的 IPv4 地址段
+    pub fn new(addr: IpAddr, prefix: u8) -> Option<Self> {
+        let max = if addr.is_ipv4() { 32 } else { 128 };
+        if prefix > max {
+            return None;
+        }
+        let (addr, prefix) = match addr.to_canonical() {
+            IpAddr::V4(v4) if addr.is_ipv6() && prefix >= 96 => (IpAddr::V4(v4), prefix - 96),
+            _ => (addr, prefix),
+        };
+        Some(IpCidr {
+            addr: mask(addr, prefix),
+            prefix,
+        })
+    }
+
+    /// 网络地址
+    #[inline]
+    pub fn addr(&self) -> IpAddr {
+        self.addr
+    }
+
+    /// 前缀长度
+    #[inline]
+    pub fn prefix(&self) -> u8 {
+        self.prefix
+    }
+
+    /// 是否包含此地址,IPv4 映射的 IPv6 地址按 IPv4 处理
+    #[inline]
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        let ip = match (self.addr, ip.to_canonical()) {
+            (IpAddr::V4(_), ip @ IpAddr::V4(_)) => ip,
+            (IpAddr::V4(_), IpAddr::V6(_)) => return false,
+            // 比 /96 更宽的 IPv6 地址段也包含 IPv4 映射地址
+            (IpAddr::V6(_), IpAddr::V4(ip)) => IpAddr::V6(ip.to_ipv6_mapped()),
+            (IpAddr::V6(_), ip @ IpAddr::V6(_)) => ip,
+        };
+        mask(ip, self.prefix) == self.addr
+    }
+}
+
+/// 取地址的前 prefix 位
+pub(crate) fn mask(ip: IpAddr, prefix: u8) -> IpAddr {
+    match ip {
+        IpAddr::V4(ip) => {
+            let mask = u32::MAX.checked_shl(32 - prefix as u32).unwrap_or(0);
+            IpAddr::V4((u32::from(ip) & mask).into())
+        }
+        IpAddr::V6(ip) => {
+            let mask = u128::MAX.checked_shl(128 - prefix as u32).unwrap_or(0);
+            IpAddr::V6((u128::from(ip) & mask).into())
+        }
+    }
+}
+
+impl FromStr for IpCidr {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let invalid = || Error::AccessListError(format!("invalid cidr:{}", s));
+        let (addr, prefix) = match s.split_once('/') {
+            Some((addr, prefix)) => (addr, Some(prefix)),
+            None => (s, None),
+        };
+        let addr = IpAddr::from_str(addr.trim()).map_err(|_| invalid())?;
+        let prefix = match prefix {
+            Some(prefix) => prefix.trim().parse().map_err(|_| invalid())?,
+            None if addr.is_ipv4() => 32,
+            None => 128,
+        };
+        IpCidr::new(addr, prefix).ok_or_else(invalid)
+    }
+}
+
+impl fmt::Display for IpCidr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.addr, self.prefix)
+    }
+}
+
+/// IP 访问控制列表,按顺序匹配,第一条命中的规则生效,都不命中时使用默认动作
+#[derive(Debug, Clone)]
+pub struct AccessList {
+    rules: Vec<(AclAction, IpCidr)>,
+    default: AclAction,
+}
+
+impl Default for AccessList {
+    fn default() -> Self {
+        AccessList {
+            rules: Vec::new(),
+            default: AclAction::Allow,
+        }
+    }
+}
+
+impl AccessList {
+    /// 默认允许的空列表
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 设置默认动作
+    pub fn default_action(mut self, action: AclAction) -> Self {
+        self.default = action;
+        self
+    }
+
+    /// 追加允许规则
+    pub fn allow(mut self, cidr: IpCidr) -> Self {
+        self.rules.push((AclAction::Allow, cidr));
+        self
+    }
+
+    /// 追加拒绝规则
+    pub fn deny(mut self, cidr: IpCidr) -> Self {
+        self.rules.push((AclAction::Deny, cidr));
+        self
+    }
+
+    /// 从文件加载,格式见 [`AccessList::parse`]
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self> {
+        Self::parse(&std::fs::read_to_string(path)?)
+    }
+
+    /// 解析规则文本,每行一条规则,# 开头为注释:
+    /// ```text
+    /// # 默认动作
+    /// default deny
+    /// allow 10.0.0.0/8
+    /// deny 192.168.1.5
+    /// allow ::1
+    /// ```
+    pub fn parse(text: &str) -> Result<Self> {
+        let mut acl = AccessList::new();
+        for (index, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or_default().trim();
+            if line.is_empty() {
+                continue;
+            }
+            let error = |msg: String| Error::AccessListError(format!("line {}:{}", index + 1, msg));
+            let (action, value) = line
+                .split_once(char::is_whitespace)
+                .ok_or_else(|| error(format!("invalid rule:{}", line)))?;
+            let value = value.trim();
+            match action {
+                "allow" => acl.rules.push((
+                    AclAction::Allow,
+                    value.parse().map_err(|err| error(format!("{}", err)))?,
+                )),
+                "deny" => acl.rules.push((
+                    AclAction::Deny,
+                    value.parse().map_err(|err| error(format!("{}", err)))?,
+                )),
+                "default" => {
+                    acl.default = match value {
+                        "allow" => AclAction::Allow,
+                        "deny" => AclAction::Deny,
+                        _ => return Err(error(format!("invalid default action:{}", value))),
+                    }
+                }
+                _ => return Err(error(format!("invalid action:{}", action))),
+            }
+        }
+        Ok(acl)
+    }
+
+    /// 检查地址
+    pub fn check(&self, ip: IpAddr) -> AclAction {
+        self.rules
+            .iter()
+            .find(|(_, cidr)| cidr.contains(ip))
+            .map_or(self.default, |(action, _)| *action)
+    }
+
+    /// 是否允许此地址连接
+    #[inline]
+    pub fn is_allowed(&self, ip: IpAddr) -> bool {
+        self.check(ip) == AclAction::Allow
+    }
+}
+
+/// 可以在运行时整体替换的访问控制列表
+#[derive(Default)]
+pub(crate) struct SharedAccessList(RwLock<Option<Arc<AccessList>>>);
+
+impl SharedAccessList {
+    pub(crate) fn new(acl: Option<AccessList>) -> Self {
+        SharedAccessList(RwLock::new(acl.map(Arc::new)))
+    }
+
+    /// 替换列表,None 表示不做限制
+    #[inline]
+    pub(crate) fn store(&self, acl: Option<AccessList>) {
+        *self.0.write().unwrap() = acl.map(Arc::new);
+    }
+
+    /// 当前列表
+    #[inline]
+    pub(crate) fn load(&self) -> Option<Arc<AccessList>> {
+        self.0.read().unwrap().clone()
+    }
+
+    /// 是否允许此地址连接
+    #[inline]
+    pub(crate) fn is_allowed(&self, ip: IpAddr) -> bool {
+        match *self.0.read().unwrap() {
+            Some(ref acl) => acl.is_allowed(ip),
+            None => true,
+        }
+    }
+}
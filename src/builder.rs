@@ -1,16 +1,23 @@
-use crate::{ConnectEventType, TCPPeer, TCPServer};
+use crate::acl::AccessList;
+use crate::connlimit::ConnectRateLimit;
+use crate::dynpeer::BoxFuture;
+use crate::handler::{ConnectionHandler, HandlerInput};
+use crate::listener::{Bind, Listener, PeerAddr};
+use crate::ratelimit::{Limiter, RateLimit};
+use crate::tcpserver::ServerOptions;
+use crate::{ConnectEventType, DisconnectReason, PeerReader, TCPPeer, TCPServer};
 
 use aqueue::Actor;
 use std::future::Future;
 use std::marker::PhantomData;
 use std::sync::Arc;
-use tokio::io::{AsyncRead, AsyncWrite, ReadHalf};
-use tokio::net::{TcpStream, ToSocketAddrs};
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncWrite};
 
 /// TCP server builder
 pub struct Builder<I, R, A, T, B, C, IST> {
     input: Option<I>,
-    connect_event: Option<ConnectEventType>,
+    options: ServerOptions<C, T>,
     stream_init: Option<IST>,
     addr: A,
     _phantom1: PhantomData<R>,
@@ -21,18 +28,18 @@ pub struct Builder<I, R, A, T, B, C, IST> {
 
 impl<I, R, A, T, B, C, IST> Builder<I, R, A, T, B, C, IST>
 where
-    I: Fn(ReadHalf<C>, Arc<Actor<TCPPeer<C>>>, T) -> R + Send + Sync + 'static,
+    I: Fn(PeerReader<C>, Arc<TCPPeer<C>>, T) -> R + Send + Sync + 'static,
     R: Future<Output = anyhow::Result<()>> + Send + 'static,
-    A: ToSocketAddrs,
+    A: Bind,
     T: Clone + Send + 'static,
     B: Future<Output = anyhow::Result<C>> + Send + 'static,
     C: AsyncRead + AsyncWrite + Send + 'static,
-    IST: Fn(TcpStream) -> B + Send + Sync + 'static,
+    IST: Fn(<A::Listener as Listener>::Stream) -> B + Send + Sync + 'static,
 {
     pub fn new(addr: A) -> Builder<I, R, A, T, B, C, IST> {
         Builder {
             input: None,
-            connect_event: None,
+            options: Default::default(),
             stream_init: None,
             addr,
             _phantom1: Default::default(),
@@ -50,7 +57,71 @@ where
 
     /// 设置TCP server 连接事件
     pub fn set_connect_event(mut self, c: ConnectEventType) -> Self {
-        self.connect_event = Some(c);
+        self.options.connect_event = Some(Arc::new(c));
+        self
+    }
+
+    /// 设置TCP server 断线事件,每个连接只会触发一次,
+    /// 回调 panic 时和 input_event 一样交给 panic handler
+    pub fn set_disconnect_event<F, Fut>(mut self, f: F) -> Self
+    where
+        F: Fn(Arc<TCPPeer<C>>, DisconnectReason, T) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        self.options.disconnect_event = Some(Arc::new(move |peer, reason, token| {
+            Box::pin(f(peer, reason, token))
+        }));
+        self
+    }
+
+    /// 设置连接任务 panic 回调,参数为对端地址和 panic 信息
+    pub fn set_panic_handler<F>(mut self, f: F) -> Self
+    where
+        F: Fn(&PeerAddr, &str) + Send + Sync + 'static,
+    {
+        self.options.panic_handler = Some(Arc::new(f));
+        self
+    }
+
+    /// 设置 accept 资源错误(例如文件句柄耗尽)的退避时间,从 min 开始翻倍直到 max
+    pub fn set_accept_backoff(mut self, min: Duration, max: Duration) -> Self {
+        self.options.accept_backoff = (min, max.max(min));
+        self
+    }
+
+    /// 设置全服务器共享的接收限速
+    pub fn set_global_ingress_limit(mut self, limit: RateLimit) -> Self {
+        self.options.limits.global_ingress = Limiter::new(limit);
+        self
+    }
+
+    /// 设置全服务器共享的发送限速
+    pub fn set_global_egress_limit(mut self, limit: RateLimit) -> Self {
+        self.options.limits.global_egress = Limiter::new(limit);
+        self
+    }
+
+    /// 设置每个连接默认的接收限速,运行时可以通过 IPeer::set_ingress_limit 修改
+    pub fn set_peer_ingress_limit(mut self, limit: RateLimit) -> Self {
+        self.options.limits.peer_ingress = limit;
+        self
+    }
+
+    /// 设置每个连接默认的发送限速,运行时可以通过 IPeer::set_egress_limit 修改
+    pub fn set_peer_egress_limit(mut self, limit: RateLimit) -> Self {
+        self.options.limits.peer_egress = limit;
+        self
+    }
+
+    /// 设置按来源地址的新连接限速和自动封禁
+    pub fn set_connect_rate_limit(mut self, limit: ConnectRateLimit) -> Self {
+        self.options.connect_rate_limit = Some(limit);
+        self
+    }
+
+    /// 设置 IP 访问控制列表,运行时可以通过 ITCPServer::set_access_list 替换
+    pub fn set_access_list(mut self, acl: AccessList) -> Self {
+        self.options.access_list = Some(acl);
         self
     }
 
@@ -61,21 +132,44 @@ where
     }
 
     /// 生成TCPSERVER,如果没有设置 tcp input 将报错
-    pub async fn build(mut self) -> Arc<Actor<TCPServer<I, R, T, B, C, IST>>> {
+    pub async fn build(mut self) -> Arc<Actor<TCPServer<I, R, T, B, C, IST, A::Listener>>> {
         if let Some(input) = self.input.take() {
             if let Some(stream_init) = self.stream_init.take() {
-                return if let Some(connect) = self.connect_event.take() {
-                    TCPServer::new(self.addr, stream_init, input, Some(connect))
-                        .await
-                        .unwrap()
-                } else {
-                    TCPServer::new(self.addr, stream_init, input, None)
-                        .await
-                        .unwrap()
-                };
+                return TCPServer::new(self.addr, stream_init, input, self.options)
+                    .await
+                    .unwrap();
             }
             panic!("stream_init is no settings,please use set_stream_init function.");
         }
         panic!("input event is no settings,please use set_input_event function set input event.");
     }
 }
+
+impl<A, T, B, C, IST>
+    Builder<HandlerInput<C, T>, BoxFuture<'static, anyhow::Result<()>>, A, T, B, C, IST>
+where
+    A: Bind,
+    T: Clone + Send + 'static,
+    B: Future<Output = anyhow::Result<C>> + Send + 'static,
+    C: AsyncRead + AsyncWrite + Send + 'static,
+    IST: Fn(<A::Listener as Listener>::Stream) -> B + Send + Sync + 'static,
+{
+    /// 使用 ConnectionHandler 代替 input/connect/disconnect 事件,
+    /// 配合 boxed_stream_init 得到可以写出的服务器类型 HandlerServer
+    pub fn with_handler<H: ConnectionHandler<C, T>>(addr: A, handler: H) -> Self {
+        let handler = Arc::new(handler);
+        let input = handler.clone();
+        let connect = handler.clone();
+        let mut builder = Self::new(addr)
+            .set_input_event(Box::new(move |reader, peer, token| {
+                let handler = input.clone();
+                Box::pin(async move { handler.handle(reader, peer, token).await })
+            }))
+            .set_disconnect_event(move |peer, reason, token| {
+                let handler = handler.clone();
+                async move { handler.on_disconnect(peer, reason, token).await }
+            });
+        builder.options.connect_event = Some(Arc::new(move |addr| connect.on_connect(addr)));
+        builder
+    }
+}
@@ -0,0 +1,184 @@
+use crate::acl::mask;
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// 新连接限速配置,按来源地址段统计
+#[derive(Debug, Copy, Clone)]
+pub struct ConnectRateLimit {
+    /// 每个地址段每秒允许的新连接数
+    pub per_sec: u32,
+    /// 允许的突发连接数
+    pub burst: u32,
+    /// IPv4 地址段前缀长度,32 表示按单个 IP
+    pub ipv4_prefix: u8,
+    /// IPv6 地址段前缀长度,128 表示按单个 IP
+    pub ipv6_prefix: u8,
+    /// 连续被拒绝多少次后自动封禁,0 表示不封禁
+    pub ban_threshold: u32,
+    /// 自动封禁时长
+    pub ban_duration: Duration,
+}
+
+impl ConnectRateLimit {
+    /// 按单个 IP 每秒 per_sec 个连接
+    pub fn new(per_sec: u32) -> Self {
+        ConnectRateLimit {
+            per_sec,
+            burst: per_sec,
+            ipv4_prefix: 32,
+            ipv6_prefix: 128,
+            ban_threshold: 0,
+            ban_duration: Duration::from_secs(60),
+        }
+    }
+
+    /// 设置突发连接数
+    pub fn burst(mut self, burst: u32) -> Self {
+        self.burst = burst;
+        self
+    }
+
+    /// 按地址段统计
+    pub fn prefix(mut self, ipv4_prefix: u8, ipv6_prefix: u8) -> Self {
+        self.ipv4_prefix = ipv4_prefix.min(32);
+        self.ipv6_prefix = ipv6_prefix.min(128);
+        self
+    }
+
+    /// 连续被拒绝 threshold 次后封禁 duration
+    pub fn ban(mut self, threshold: u32, duration: Duration) -> Self {
+        self.ban_threshold = threshold;
+        self.ban_duration = duration;
+        self
+    }
+}
+
+/// 新连接检查结果
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub(crate) enum Admission {
+    Allowed,
+    RateLimited,
+    Banned,
+}
+
+struct Bucket {
+    tokens: f64,
+    last: Instant,
+    rejects: u32,
+}
+
+/// 超过这个数量时清理空闲的统计项
+const MAX_IDLE_ENTRIES: usize = 4096;
+
+/// accept 循环中的连接限速和封禁表
+#[derive(Default)]
+pub(crate) struct ConnectLimiter {
+    limit: Option<ConnectRateLimit>,
+    buckets: Mutex<HashMap<IpAddr, Bucket>>,
+    bans: Mutex<HashMap<IpAddr, Option<Instant>>>,
+}
+
+impl ConnectLimiter {
+    pub(crate) fn new(limit: Option<ConnectRateLimit>) -> Self {
+        ConnectLimiter {
+            limit,
+            ..Default::default()
+        }
+    }
+
+    /// 按配置的前缀长度取地址段
+    fn key(&self, ip: IpAddr) -> IpAddr {
+        let (v4, v6) = self
+            .limit
+            .map_or((32, 128), |limit| (limit.ipv4_prefix, limit.ipv6_prefix));
+        let ip = ip.to_canonical();
+        mask(ip, if ip.is_ipv4() { v4 } else { v6 })
+    }
+
+    /// 封禁地址所在的地址段,None 表示永久封禁
+    pub(crate) fn ban(&self, ip: IpAddr, duration: Option<Duration>) {
+        let key = self.key(ip);
+        let expire = duration.map(|duration| Instant::now() + duration);
+        self.bans.lock().unwrap().insert(key, expire);
+    }
+
+    /// 解除封禁,返回之前是否处于封禁状态
+    pub(crate) fn unban(&self, ip: IpAddr) -> bool {
+        let key = self.key(ip);
+        self.bans.lock().unwrap().remove(&key).is_some()
+    }
+
+    /// 当前封禁的地址段和剩余时长
+    pub(crate) fn banned(&self) -> Vec<(IpAddr, Option<Duration>)> {
+        let now = Instant::now();
+        let mut bans = self.bans.lock().unwrap();
+        bans.retain(|_, expire| match expire {
+            Some(expire) => *expire > now,
+            None => true,
+        });
+        bans.iter()
+            .map(|(ip, expire)| (*ip, expire.map(|expire| expire - now)))
+            .collect()
+    }
+
+    /// 检查新连接
+    pub(crate) fn check(&self, ip: IpAddr) -> Admission {
+        let key = self.key(ip);
+        let now = Instant::now();
+        {
+            let mut bans = self.bans.lock().unwrap();
+            match bans.get(&key) {
+                Some(None) => return Admission::Banned,
+                Some(Some(expire)) if *expire > now => return Admission::Banned,
+                Some(Some(_)) => {
+                    bans.remove(&key);
+                }
+                None => {}
+            }
+        }
+
+        let limit = match self.limit {
+            Some(limit) => limit,
+            None => return Admission::Allowed,
+        };
+        let rate = limit.per_sec.max(1) as f64;
+        let burst = limit.burst.max(1) as f64;
+        let refill = |bucket: &Bucket| {
+            (bucket.tokens + now.duration_since(bucket.last).as_secs_f64() * rate).min(burst)
+        };
+
+        let mut buckets = self.buckets.lock().unwrap();
+        if buckets.len() > MAX_IDLE_ENTRIES {
+            buckets.retain(|_, bucket| refill(bucket) < burst);
+        }
+        let bucket = buckets.entry(key).or_insert(Bucket {
+            tokens: burst,
+            last: now,
+            rejects: 0,
+        });
+        bucket.tokens = refill(bucket);
+        bucket.last = now;
+        if bucket.tokens >= burst {
+            // 安静了足够久,重新计算拒绝次数
+            bucket.rejects = 0;
+        }
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            return Admission::Allowed;
+        }
+
+        bucket.rejects += 1;
+        if limit.ban_threshold > 0 && bucket.rejects >= limit.ban_threshold {
+            bucket.rejects = 0;
+            drop(buckets);
+            self.bans
+                .lock()
+                .unwrap()
+                .insert(key, Some(now + limit.ban_duration));
+            return Admission::Banned;
+        }
+        Admission::RateLimited
+    }
+}
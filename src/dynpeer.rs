@@ -0,0 +1,100 @@
+use crate::error::Result;
+use crate::listener::PeerAddr;
+use crate::peer::{IPeer, TCPPeer};
+use crate::ratelimit::RateLimit;
+use crate::sendfile::FileSource;
+use crate::state::PeerState;
+use crate::stats::PeerStats;
+use bytes::Bytes;
+use std::future::Future;
+use std::pin::Pin;
+use tokio::io::{AsyncRead, AsyncWrite};
+
+pub type BoxFuture<'a, T> = Pin<Box<dyn Future<Output = T> + Send + 'a>>;
+
+/// IPeer 的对象安全版本,可以保存为 `Arc<dyn IDynPeer>`,不需要关心流类型
+pub trait IDynPeer: Send + Sync {
+    fn addr(&self) -> PeerAddr;
+    fn stats(&self) -> PeerStats;
+    fn set_ingress_limit(&self, limit: RateLimit);
+    fn set_egress_limit(&self, limit: RateLimit);
+    fn state(&self) -> PeerState;
+    fn is_disconnect(&self) -> BoxFuture<'_, Result<bool>>;
+    fn send(&self, buff: Bytes) -> BoxFuture<'_, Result<usize>>;
+    fn send_all(&self, buff: Bytes) -> BoxFuture<'_, Result<()>>;
+    fn flush(&self) -> BoxFuture<'_, Result<()>>;
+    fn send_file(&self, file: FileSource, offset: u64, len: u64) -> BoxFuture<'_, Result<u64>>;
+    fn shutdown_write(&self) -> BoxFuture<'_, Result<()>>;
+    fn disconnect(&self) -> BoxFuture<'_, Result<()>>;
+    fn kick<'a>(&'a self, reason: String, farewell: &'a [u8]) -> BoxFuture<'a, Result<()>>;
+}
+
+impl<T> IDynPeer for TCPPeer<T>
+where
+    T: AsyncRead + AsyncWrite + Send + 'static,
+{
+    #[inline]
+    fn addr(&self) -> PeerAddr {
+        IPeer::addr(self)
+    }
+
+    #[inline]
+    fn stats(&self) -> PeerStats {
+        IPeer::stats(self)
+    }
+
+    #[inline]
+    fn set_ingress_limit(&self, limit: RateLimit) {
+        IPeer::set_ingress_limit(self, limit)
+    }
+
+    #[inline]
+    fn set_egress_limit(&self, limit: RateLimit) {
+        IPeer::set_egress_limit(self, limit)
+    }
+
+    #[inline]
+    fn state(&self) -> PeerState {
+        IPeer::state(self)
+    }
+
+    #[inline]
+    fn is_disconnect(&self) -> BoxFuture<'_, Result<bool>> {
+        Box::pin(IPeer::is_disconnect(self))
+    }
+
+    #[inline]
+    fn send(&self, buff: Bytes) -> BoxFuture<'_, Result<usize>> {
+        Box::pin(IPeer::send(self, buff))
+    }
+
+    #[inline]
+    fn send_all(&self, buff: Bytes) -> BoxFuture<'_, Result<()>> {
+        Box::pin(IPeer::send_all(self, buff))
+    }
+
+    #[inline]
+    fn flush(&self) -> BoxFuture<'_, Result<()>> {
+        Box::pin(IPeer::flush(self))
+    }
+
+    #[inline]
+    fn send_file(&self, file: FileSource, offset: u64, len: u64) -> BoxFuture<'_, Result<u64>> {
+        Box::pin(IPeer::send_file(self, file, offset, len))
+    }
+
+    #[inline]
+    fn shutdown_write(&self) -> BoxFuture<'_, Result<()>> {
+        Box::pin(IPeer::shutdown_write(self))
+    }
+
+    #[inline]
+    fn disconnect(&self) -> BoxFuture<'_, Result<()>> {
+        Box::pin(IPeer::disconnect(self))
+    }
+
+    #[inline]
+    fn kick<'a>(&'a self, reason: String, farewell: &'a [u8]) -> BoxFuture<'a, Result<()>> {
+        Box::pin(IPeer::kick(self, reason, farewell))
+    }
+}
@@ -8,6 +8,12 @@ pub enum Error {
     JoinError(#[from] tokio::task::JoinError),
     #[error("not listener or repeat start")]
     NotListenerError,
+    #[error("access list error:{0}")]
+    AccessListError(String),
+    #[error("idle timeout")]
+    IdleTimeout,
+    #[error("read buffer exceeds {0} bytes")]
+    ReadBufferFull(usize),
 }
 
 pub type Result<T, E = Error> = core::result::Result<T, E>;
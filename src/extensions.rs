@@ -0,0 +1,72 @@
+use std::any::{Any, TypeId};
+use std::collections::HashMap;
+
+/// 按类型存储的扩展数据,每种类型最多保存一个值
+#[derive(Default)]
+pub struct Extensions {
+    map: HashMap<TypeId, Box<dyn Any + Send + Sync>>,
+}
+
+impl Extensions {
+    /// 创建一个空的扩展表
+    #[inline]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 插入值,如果已经存在同类型的值返回旧值
+    #[inline]
+    pub fn insert<E: Send + Sync + 'static>(&mut self, val: E) -> Option<E> {
+        self.map
+            .insert(TypeId::of::<E>(), Box::new(val))
+            .and_then(|old| old.downcast().ok().map(|old| *old))
+    }
+
+    /// 获取引用
+    #[inline]
+    pub fn get<E: Send + Sync + 'static>(&self) -> Option<&E> {
+        self.map
+            .get(&TypeId::of::<E>())
+            .and_then(|val| val.downcast_ref())
+    }
+
+    /// 获取可变引用
+    #[inline]
+    pub fn get_mut<E: Send + Sync + 'static>(&mut self) -> Option<&mut E> {
+        self.map
+            .get_mut(&TypeId::of::<E>())
+            .and_then(|val| val.downcast_mut())
+    }
+
+    /// 删除并返回值
+    #[inline]
+    pub fn remove<E: Send + Sync + 'static>(&mut self) -> Option<E> {
+        self.map
+            .remove(&TypeId::of::<E>())
+            .and_then(|old| old.downcast().ok().map(|old| *old))
+    }
+
+    /// 是否包含此类型
+    #[inline]
+    pub fn contains<E: Send + Sync + 'static>(&self) -> bool {
+        self.map.contains_key(&TypeId::of::<E>())
+    }
+
+    /// 清空
+    #[inline]
+    pub fn clear(&mut self) {
+        self.map.clear()
+    }
+
+    /// 是否为空
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.map.is_empty()
+    }
+
+    /// 数量
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.map.len()
+    }
+}
@@ -0,0 +1,66 @@
+use crate::dynpeer::BoxFuture;
+use crate::listener::{Listener, PeerAddr};
+use crate::peer::TCPPeer;
+use crate::reader::PeerReader;
+use crate::tcpserver::{DisconnectReason, TCPServer};
+use std::future::Future;
+use std::sync::Arc;
+use tokio::net::TcpListener;
+
+/// 连接处理器,通过 Builder::with_handler 代替 input/connect/disconnect 闭包
+pub trait ConnectionHandler<C, T>: Send + Sync + 'static {
+    /// 新连接,返回 false 拒绝连接
+    #[inline]
+    fn on_connect(&self, _addr: &PeerAddr) -> bool {
+        true
+    }
+
+    /// 处理连接,返回后断开连接
+    fn handle(
+        &self,
+        reader: PeerReader<C>,
+        peer: Arc<TCPPeer<C>>,
+        token: T,
+    ) -> impl Future<Output = anyhow::Result<()>> + Send;
+
+    /// 断线,每个连接只会触发一次
+    #[inline]
+    fn on_disconnect(
+        &self,
+        _peer: Arc<TCPPeer<C>>,
+        _reason: DisconnectReason,
+        _token: T,
+    ) -> impl Future<Output = ()> + Send {
+        async {}
+    }
+}
+
+/// ConnectionHandler 包装成的 input 事件
+pub type HandlerInput<C, T> = Box<
+    dyn Fn(PeerReader<C>, Arc<TCPPeer<C>>, T) -> BoxFuture<'static, anyhow::Result<()>>
+        + Send
+        + Sync,
+>;
+
+/// 装箱的 stream_init,可以写出服务器类型
+pub type StreamInit<S, C> = Box<dyn Fn(S) -> BoxFuture<'static, anyhow::Result<C>> + Send + Sync>;
+
+/// 使用 ConnectionHandler 和 StreamInit 的服务器,例如 `Arc<Actor<HandlerServer<TcpStream, ()>>>`
+pub type HandlerServer<C, T, L = TcpListener> = TCPServer<
+    HandlerInput<C, T>,
+    BoxFuture<'static, anyhow::Result<()>>,
+    T,
+    BoxFuture<'static, anyhow::Result<C>>,
+    C,
+    StreamInit<<L as Listener>::Stream, C>,
+    L,
+>;
+
+/// 把 stream_init 闭包装箱为 StreamInit
+pub fn boxed_stream_init<S, C, F, Fut>(f: F) -> StreamInit<S, C>
+where
+    F: Fn(S) -> Fut + Send + Sync + 'static,
+    Fut: Future<Output = anyhow::Result<C>> + Send + 'static,
+{
+    Box::new(move |stream| Box::pin(f(stream)))
+}
@@ -0,0 +1,159 @@
+//! metrics 和 tracing 埋点,未开启对应 feature 时全部为空操作
+
+use crate::listener::PeerAddr;
+use std::future::Future;
+use std::time::Duration;
+
+/// 接受新连接
+#[inline]
+pub(crate) fn connection_accepted() {
+    #[cfg(feature = "metrics")]
+    metrics::counter!("tcpserver_connections_accepted_total").increment(1);
+}
+
+/// 拒绝新连接
+#[inline]
+pub(crate) fn connection_rejected(reason: &'static str) {
+    #[cfg(not(feature = "metrics"))]
+    let _ = reason;
+    #[cfg(feature = "metrics")]
+    metrics::counter!("tcpserver_connections_rejected_total", "reason" => reason).increment(1);
+}
+
+/// accept 出错
+#[inline]
+pub(crate) fn accept_error() {
+    #[cfg(feature = "metrics")]
+    metrics::counter!("tcpserver_accept_errors_total").increment(1);
+}
+
+/// stream_init 成功,记录握手耗时
+#[inline]
+pub(crate) fn handshake_done(elapsed: Duration) {
+    #[cfg(not(feature = "metrics"))]
+    let _ = elapsed;
+    #[cfg(feature = "metrics")]
+    metrics::histogram!("tcpserver_handshake_duration_seconds").record(elapsed.as_secs_f64());
+}
+
+/// stream_init 失败
+#[inline]
+pub(crate) fn handshake_failed() {
+    #[cfg(feature = "metrics")]
+    metrics::counter!("tcpserver_handshake_failures_total").increment(1);
+}
+
+/// 连接建立
+#[inline]
+pub(crate) fn connection_opened() {
+    #[cfg(feature = "metrics")]
+    metrics::gauge!("tcpserver_connections_active").increment(1.0);
+}
+
+/// 连接关闭,记录连接存活时间
+#[inline]
+pub(crate) fn connection_closed(elapsed: Duration) {
+    #[cfg(not(feature = "metrics"))]
+    let _ = elapsed;
+    #[cfg(feature = "metrics")]
+    {
+        metrics::gauge!("tcpserver_connections_active").decrement(1.0);
+        metrics::histogram!("tcpserver_connection_duration_seconds").record(elapsed.as_secs_f64());
+    }
+}
+
+/// 连接任务 panic
+#[inline]
+pub(crate) fn task_panic() {
+    #[cfg(feature = "metrics")]
+    metrics::counter!("tcpserver_panics_total").increment(1);
+}
+
+/// 读取字节
+#[inline]
+pub(crate) fn bytes_received(len: usize) {
+    #[cfg(not(feature = "metrics"))]
+    let _ = len;
+    #[cfg(feature = "metrics")]
+    metrics::counter!("tcpserver_bytes_received_total").increment(len as u64);
+}
+
+/// 发送字节
+#[inline]
+pub(crate) fn bytes_sent(len: u64) {
+    #[cfg(not(feature = "metrics"))]
+    let _ = len;
+    #[cfg(feature = "metrics")]
+    metrics::counter!("tcpserver_bytes_sent_total").increment(len);
+}
+
+/// 完整发送一条消息,记录耗时
+#[inline]
+pub(crate) fn message_sent(elapsed: Duration) {
+    #[cfg(not(feature = "metrics"))]
+    let _ = elapsed;
+    #[cfg(feature = "metrics")]
+    {
+        metrics::counter!("tcpserver_messages_sent_total").increment(1);
+        metrics::histogram!("tcpserver_send_duration_seconds").record(elapsed.as_secs_f64());
+    }
+}
+
+/// 每个连接的 tracing span,未开启 `tracing` feature 时为空
+#[derive(Clone)]
+pub(crate) struct ConnectionSpan {
+    #[cfg(feature = "tracing")]
+    span: tracing::Span,
+}
+
+impl ConnectionSpan {
+    #[inline]
+    pub(crate) fn new(addr: &PeerAddr, conn_id: u64, listener: Option<&PeerAddr>) -> Self {
+        #[cfg(not(feature = "tracing"))]
+        let _ = (addr, conn_id, listener);
+        ConnectionSpan {
+            #[cfg(feature = "tracing")]
+            span: tracing::info_span!(
+                "connection",
+                peer = %addr,
+                conn_id,
+                listener = tracing::field::display(
+                    listener.map_or(String::new(), |listener| listener.to_string())
+                )
+            ),
+        }
+    }
+
+    /// 整个连接任务
+    #[inline]
+    pub(crate) fn connection<F: Future>(&self, fut: F) -> impl Future<Output = F::Output> {
+        #[cfg(feature = "tracing")]
+        return tracing::Instrument::instrument(fut, self.span.clone());
+        #[cfg(not(feature = "tracing"))]
+        fut
+    }
+
+    /// stream_init
+    #[inline]
+    pub(crate) fn stream_init<F: Future>(&self, fut: F) -> impl Future<Output = F::Output> {
+        #[cfg(feature = "tracing")]
+        return tracing::Instrument::instrument(
+            fut,
+            tracing::info_span!(parent: &self.span, "stream_init"),
+        );
+        #[cfg(not(feature = "tracing"))]
+        fut
+    }
+
+    /// input_event
+    #[inline]
+    pub(crate) fn input_event<F: Future>(&self, fut: F) -> impl Future<Output = F::Output> {
+        #[cfg(feature = "tracing")]
+        return tracing::Instrument::instrument(
+            fut,
+            tracing::info_span!(parent: &self.span, "input_event"),
+        );
+        #[cfg(not(feature = "tracing"))]
+        fut
+    }
+}
@@ -1,8 +1,50 @@
+mod acl;
 mod builder;
+mod connlimit;
+mod dynpeer;
 pub mod error;
+mod extensions;
+mod handler;
+mod instrument;
+mod lifecycle;
+mod listener;
 mod peer;
+mod ratelimit;
+mod reader;
+mod readloop;
+mod sendfile;
+mod sniff;
+mod state;
+mod stats;
+mod stream;
 mod tcpserver;
+mod transport;
+mod udpbuilder;
+mod udpserver;
+#[cfg(feature = "websocket")]
+pub mod websocket;
 
+pub use acl::{AccessList, AclAction, IpCidr};
 pub use builder::Builder;
+pub use bytes::{Bytes, BytesMut};
+pub use connlimit::ConnectRateLimit;
+pub use dynpeer::{BoxFuture, IDynPeer};
+pub use extensions::Extensions;
+pub use handler::{boxed_stream_init, ConnectionHandler, HandlerInput, HandlerServer, StreamInit};
+pub use lifecycle::ServerState;
+pub use listener::{Bind, Listener, PeerAddr, Rebind};
+#[cfg(unix)]
+pub use listener::{UnixBind, UnixCredentials, UnixSocketListener};
 pub use peer::*;
+pub use ratelimit::RateLimit;
+pub use reader::PeerReader;
+pub use readloop::{ReadHandler, ReadLoop};
+pub use sendfile::FileSource;
+pub use sniff::{sniff, Protocol, SniffedStream, Sniffer};
+pub use state::PeerState;
+pub use stats::PeerStats;
+pub use stream::{AsyncStream, BoxPeer, BoxStream};
 pub use tcpserver::*;
+pub use transport::ITransportPeer;
+pub use udpbuilder::UdpBuilder;
+pub use udpserver::{IUdpServer, UdpPeer, UdpReader, UdpServer};
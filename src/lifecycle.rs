@@ -0,0 +1,188 @@
+use log::*;
+use std::collections::HashMap;
+use std::io;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::Notify;
+use tokio::task::AbortHandle;
+
+/// 服务器生命周期状态,创建后尚未启动时为 Stopped(None)
+#[derive(Debug, Clone)]
+pub enum ServerState {
+    /// 已调用 start,accept 任务还没有运行
+    Starting,
+    /// 正在运行,pause_accept 不影响此状态
+    Running,
+    /// 已调用 shutdown,不再接受新连接,等待已有连接结束
+    Draining,
+    /// 已停止,accept 循环出错退出时附带错误
+    Stopped(Option<Arc<io::Error>>),
+}
+
+impl ServerState {
+    #[inline]
+    pub fn is_running(&self) -> bool {
+        matches!(self, ServerState::Running)
+    }
+
+    #[inline]
+    pub fn is_stopped(&self) -> bool {
+        matches!(self, ServerState::Stopped(_))
+    }
+
+    /// 导致服务器停止的错误
+    #[inline]
+    pub fn error(&self) -> Option<&io::Error> {
+        match self {
+            ServerState::Stopped(Some(err)) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+/// 中止 input 任务后,留给断线处理(disconnect 和 disconnect_event)的时间
+const DISCONNECT_TIMEOUT: Duration = Duration::from_secs(1);
+
+#[derive(Default)]
+struct Tasks {
+    next_id: u64,
+    /// 超过等待时间后不再允许新的握手或者 input 任务运行
+    closing: bool,
+    handles: HashMap<u64, Handles>,
+}
+
+/// 一个连接的任务
+#[derive(Default)]
+struct Handles {
+    /// 当前阶段的任务(握手或者 input)
+    task: Option<AbortHandle>,
+    /// 断线事件任务
+    disconnect: Option<AbortHandle>,
+    /// 整个连接的任务
+    connection: Option<AbortHandle>,
+}
+
+/// 连接登记,停止服务时等待连接结束或者强制关闭
+#[derive(Default)]
+pub(crate) struct Connections {
+    tasks: Mutex<Tasks>,
+    idle: Notify,
+}
+
+impl Connections {
+    /// 登记新连接,guard 释放时注销
+    pub(crate) fn open(self: &Arc<Self>) -> ConnectionGuard {
+        let mut tasks = self.tasks.lock().unwrap();
+        let id = tasks.next_id;
+        tasks.next_id += 1;
+        tasks.handles.insert(id, Handles::default());
+        ConnectionGuard {
+            id,
+            connections: self.clone(),
+        }
+    }
+
+    /// 登记整个连接的任务,连接已经结束时忽略
+    pub(crate) fn set_connection(&self, id: u64, handle: AbortHandle) {
+        if let Some(handles) = self.tasks.lock().unwrap().handles.get_mut(&id) {
+            handles.connection = Some(handle);
+        }
+    }
+
+    /// 当前连接数
+    #[inline]
+    pub(crate) fn len(&self) -> usize {
+        self.tasks.lock().unwrap().handles.len()
+    }
+
+    /// 等待所有连接结束,超过 grace 后中止剩余连接的握手和 input 任务,
+    /// 断线处理超过 DISCONNECT_TIMEOUT 仍未结束时中止整个连接任务
+    pub(crate) async fn drain(&self, grace: Duration) {
+        if tokio::time::timeout(grace, self.wait_idle()).await.is_ok() {
+            return;
+        }
+        {
+            let mut tasks = self.tasks.lock().unwrap();
+            tasks.closing = true;
+            for handle in tasks.handles.values().filter_map(|h| h.task.as_ref()) {
+                handle.abort();
+            }
+        }
+        if tokio::time::timeout(DISCONNECT_TIMEOUT, self.wait_idle())
+            .await
+            .is_err()
+        {
+            warn!(
+                "disconnect not finished in time, abort {} connections",
+                self.len()
+            );
+            let tasks = self.tasks.lock().unwrap();
+            for handles in tasks.handles.values() {
+                for handle in handles
+                    .task
+                    .iter()
+                    .chain(handles.disconnect.iter())
+                    .chain(handles.connection.iter())
+                {
+                    handle.abort();
+                }
+            }
+        }
+        self.wait_idle().await;
+        self.tasks.lock().unwrap().closing = false;
+    }
+
+    async fn wait_idle(&self) {
+        loop {
+            let notified = self.idle.notified();
+            tokio::pin!(notified);
+            notified.as_mut().enable();
+            if self.len() == 0 {
+                return;
+            }
+            notified.await;
+        }
+    }
+}
+
+/// 连接登记项
+pub(crate) struct ConnectionGuard {
+    id: u64,
+    connections: Arc<Connections>,
+}
+
+impl ConnectionGuard {
+    #[inline]
+    pub(crate) fn id(&self) -> u64 {
+        self.id
+    }
+
+    /// 登记当前阶段的任务(握手或者 input),已经开始强制关闭时立即中止
+    pub(crate) fn set_task(&self, handle: AbortHandle) {
+        let mut tasks = self.connections.tasks.lock().unwrap();
+        if tasks.closing {
+            handle.abort();
+        }
+        if let Some(handles) = tasks.handles.get_mut(&self.id) {
+            handles.task = Some(handle);
+        }
+    }
+
+    /// 登记断线事件任务,强制关闭时仍然运行,直到断线处理超时
+    pub(crate) fn set_disconnect_task(&self, handle: AbortHandle) {
+        let mut tasks = self.connections.tasks.lock().unwrap();
+        if let Some(handles) = tasks.handles.get_mut(&self.id) {
+            handles.disconnect = Some(handle);
+        }
+    }
+}
+
+impl Drop for ConnectionGuard {
+    fn drop(&mut self) {
+        let mut tasks = self.connections.tasks.lock().unwrap();
+        tasks.handles.remove(&self.id);
+        if tasks.handles.is_empty() {
+            self.connections.idle.notify_waiters();
+        }
+    }
+}
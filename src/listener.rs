@@ -0,0 +1,340 @@
+use crate::dynpeer::BoxFuture;
+use std::fmt;
+use std::future::Future;
+use std::io;
+use std::net::{IpAddr, SocketAddr};
+#[cfg(unix)]
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::net::{TcpListener, TcpStream, ToSocketAddrs};
+
+/// 对端地址,TCP、UDP 或 Unix domain socket
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum PeerAddr {
+    Tcp(SocketAddr),
+    /// UDP 虚拟连接的对端地址
+    Udp(SocketAddr),
+    #[cfg(unix)]
+    Unix {
+        /// 对端绑定的路径,客户端一般没有
+        path: Option<PathBuf>,
+        /// 对端进程凭据
+        cred: Option<UnixCredentials>,
+    },
+}
+
+/// Unix domain socket 对端进程凭据
+#[cfg(unix)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct UnixCredentials {
+    pub uid: u32,
+    pub gid: u32,
+    pub pid: Option<i32>,
+}
+
+impl PeerAddr {
+    /// TCP 地址
+    #[inline]
+    pub fn as_tcp(&self) -> Option<SocketAddr> {
+        match self {
+            PeerAddr::Tcp(addr) => Some(*addr),
+            _ => None,
+        }
+    }
+
+    /// UDP 地址
+    #[inline]
+    pub fn as_udp(&self) -> Option<SocketAddr> {
+        match self {
+            PeerAddr::Udp(addr) => Some(*addr),
+            _ => None,
+        }
+    }
+
+    /// TCP 或 UDP 的 socket 地址
+    #[inline]
+    pub fn socket_addr(&self) -> Option<SocketAddr> {
+        match self {
+            PeerAddr::Tcp(addr) | PeerAddr::Udp(addr) => Some(*addr),
+            #[cfg(unix)]
+            PeerAddr::Unix { .. } => None,
+        }
+    }
+
+    /// TCP 或 UDP 对端 IP
+    #[inline]
+    pub fn ip(&self) -> Option<IpAddr> {
+        self.socket_addr().map(|addr| addr.ip())
+    }
+
+    /// Unix 对端进程凭据
+    #[cfg(unix)]
+    #[inline]
+    pub fn unix_cred(&self) -> Option<UnixCredentials> {
+        match self {
+            PeerAddr::Unix { cred, .. } => *cred,
+            _ => None,
+        }
+    }
+}
+
+impl From<SocketAddr> for PeerAddr {
+    #[inline]
+    fn from(addr: SocketAddr) -> Self {
+        PeerAddr::Tcp(addr)
+    }
+}
+
+impl PartialEq<SocketAddr> for PeerAddr {
+    #[inline]
+    fn eq(&self, other: &SocketAddr) -> bool {
+        self.socket_addr().as_ref() == Some(other)
+    }
+}
+
+impl fmt::Display for PeerAddr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PeerAddr::Tcp(addr) => write!(f, "{}", addr),
+            PeerAddr::Udp(addr) => write!(f, "udp:{}", addr),
+            #[cfg(unix)]
+            PeerAddr::Unix { path, cred } => {
+                write!(f, "unix:")?;
+                if let Some(path) = path {
+                    write!(f, "{}", path.display())?;
+                }
+                if let Some(cred) = cred {
+                    write!(f, "(uid={} gid={}", cred.uid, cred.gid)?;
+                    if let Some(pid) = cred.pid {
+                        write!(f, " pid={}", pid)?;
+                    }
+                    write!(f, ")")?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+/// 按相同配置重新监听,可以多次调用
+pub type Rebind<L> = Arc<dyn Fn() -> BoxFuture<'static, io::Result<L>> + Send + Sync>;
+
+/// 服务器使用的监听器
+pub trait Listener: Send + Sync + 'static {
+    /// accept 得到的原始流,传给 stream_init
+    type Stream: AsyncRead + AsyncWrite + Send + 'static;
+
+    /// 接受新连接,需要可以安全取消
+    fn accept(&self) -> impl Future<Output = io::Result<(Self::Stream, PeerAddr)>> + Send;
+
+    /// 监听地址
+    fn local_addr(&self) -> io::Result<PeerAddr>;
+
+    /// 重新监听的方法,pause_accept(PauseMode::CloseListener) 之后恢复和停止后重新启动时使用,
+    /// 默认不支持
+    fn rebind(&self) -> Rebind<Self>
+    where
+        Self: Sized,
+    {
+        Arc::new(|| Box::pin(async { Err(io::Error::from(io::ErrorKind::Unsupported)) }))
+    }
+}
+
+impl Listener for TcpListener {
+    type Stream = TcpStream;
+
+    #[inline]
+    async fn accept(&self) -> io::Result<(TcpStream, PeerAddr)> {
+        let (stream, addr) = TcpListener::accept(self).await?;
+        Ok((stream, PeerAddr::Tcp(addr)))
+    }
+
+    #[inline]
+    fn local_addr(&self) -> io::Result<PeerAddr> {
+        Ok(PeerAddr::Tcp(TcpListener::local_addr(self)?))
+    }
+
+    /// 绑定到当前的监听地址,端口为 0 时使用实际分配的端口
+    fn rebind(&self) -> Rebind<Self> {
+        let addr = TcpListener::local_addr(self).map_err(|err| err.kind());
+        Arc::new(move || {
+            Box::pin(async move {
+                let addr = addr?;
+                TcpListener::bind(addr).await
+            })
+        })
+    }
+}
+
+/// 可以绑定为监听器的地址,TCP 地址或 [`UnixBind`]
+pub trait Bind {
+    type Listener: Listener;
+
+    /// 绑定
+    fn bind(self) -> impl Future<Output = io::Result<Self::Listener>>;
+}
+
+impl<A: ToSocketAddrs> Bind for A {
+    type Listener = TcpListener;
+
+    #[inline]
+    async fn bind(self) -> io::Result<TcpListener> {
+        TcpListener::bind(self).await
+    }
+}
+
+/// Unix domain socket 监听配置
+#[cfg(unix)]
+#[derive(Debug, Clone)]
+pub struct UnixBind {
+    path: PathBuf,
+    mode: Option<u32>,
+    remove_existing: bool,
+    cleanup: bool,
+}
+
+#[cfg(unix)]
+impl UnixBind {
+    /// 监听路径,默认删除遗留的 socket 文件,关闭时清理
+    pub fn new<P: AsRef<Path>>(path: P) -> Self {
+        UnixBind {
+            path: path.as_ref().to_path_buf(),
+            mode: None,
+            remove_existing: true,
+            cleanup: true,
+        }
+    }
+
+    /// 设置 socket 文件权限,例如 0o660
+    pub fn mode(mut self, mode: u32) -> Self {
+        self.mode = Some(mode);
+        self
+    }
+
+    /// 绑定前是否删除已存在的 socket 文件
+    pub fn remove_existing(mut self, remove: bool) -> Self {
+        self.remove_existing = remove;
+        self
+    }
+
+    /// 监听器释放时是否删除 socket 文件
+    pub fn cleanup(mut self, cleanup: bool) -> Self {
+        self.cleanup = cleanup;
+        self
+    }
+}
+
+#[cfg(unix)]
+impl Bind for UnixBind {
+    type Listener = UnixSocketListener;
+
+    async fn bind(self) -> io::Result<UnixSocketListener> {
+        use std::os::unix::fs::FileTypeExt;
+
+        if self.remove_existing {
+            if let Ok(meta) = std::fs::symlink_metadata(&self.path) {
+                if meta.file_type().is_socket() {
+                    std::fs::remove_file(&self.path)?;
+                }
+            }
+        }
+        let inner = match self.mode {
+            Some(mode) => bind_with_mode(&self.path, mode)?,
+            None => tokio::net::UnixListener::bind(&self.path)?,
+        };
+        Ok(UnixSocketListener {
+            inner,
+            path: self.path,
+            mode: self.mode,
+            remove_existing: self.remove_existing,
+            cleanup: self.cleanup,
+        })
+    }
+}
+
+/// 在权限 0700 的临时目录中绑定并设置权限,再硬链接到目标路径,
+/// socket 文件出现在目标路径之前权限已经生效,目标已存在时返回 AddrInUse
+#[cfg(unix)]
+fn bind_with_mode(path: &Path, mode: u32) -> io::Result<tokio::net::UnixListener> {
+    use std::os::unix::fs::{DirBuilderExt, PermissionsExt};
+    use std::sync::atomic::{AtomicU64, Ordering};
+
+    static NEXT_DIR: AtomicU64 = AtomicU64::new(0);
+    let parent = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    let dir = parent.join(format!(
+        ".{}-{}",
+        std::process::id(),
+        NEXT_DIR.fetch_add(1, Ordering::Relaxed)
+    ));
+    std::fs::DirBuilder::new().mode(0o700).create(&dir)?;
+    let tmp = dir.join("s");
+    let result = tokio::net::UnixListener::bind(&tmp).and_then(|listener| {
+        std::fs::set_permissions(&tmp, std::fs::Permissions::from_mode(mode))?;
+        std::fs::hard_link(&tmp, path).map_err(|err| match err.kind() {
+            io::ErrorKind::AlreadyExists => io::ErrorKind::AddrInUse.into(),
+            _ => err,
+        })?;
+        Ok(listener)
+    });
+    let _ = std::fs::remove_file(&tmp);
+    let _ = std::fs::remove_dir(&dir);
+    result
+}
+
+/// Unix domain socket 监听器
+#[cfg(unix)]
+pub struct UnixSocketListener {
+    inner: tokio::net::UnixListener,
+    path: PathBuf,
+    mode: Option<u32>,
+    remove_existing: bool,
+    cleanup: bool,
+}
+
+#[cfg(unix)]
+impl Listener for UnixSocketListener {
+    type Stream = tokio::net::UnixStream;
+
+    async fn accept(&self) -> io::Result<(tokio::net::UnixStream, PeerAddr)> {
+        let (stream, addr) = self.inner.accept().await?;
+        let cred = stream.peer_cred().ok().map(|cred| UnixCredentials {
+            uid: cred.uid(),
+            gid: cred.gid(),
+            pid: cred.pid(),
+        });
+        let path = addr.as_pathname().map(Path::to_path_buf);
+        Ok((stream, PeerAddr::Unix { path, cred }))
+    }
+
+    #[inline]
+    fn local_addr(&self) -> io::Result<PeerAddr> {
+        Ok(PeerAddr::Unix {
+            path: Some(self.path.clone()),
+            cred: None,
+        })
+    }
+
+    /// 使用原来的路径、权限和绑定选项
+    fn rebind(&self) -> Rebind<Self> {
+        let bind = UnixBind {
+            path: self.path.clone(),
+            mode: self.mode,
+            remove_existing: self.remove_existing,
+            cleanup: self.cleanup,
+        };
+        Arc::new(move || Box::pin(bind.clone().bind()))
+    }
+}
+
+#[cfg(unix)]
+impl Drop for UnixSocketListener {
+    fn drop(&mut self) {
+        if self.cleanup {
+            let _ = std::fs::remove_file(&self.path);
+        }
+    }
+}
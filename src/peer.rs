@@ -1,15 +1,47 @@
 use crate::error::Result;
+use crate::extensions::Extensions;
+use crate::instrument;
+use crate::listener::PeerAddr;
+use crate::ratelimit::{RateLimit, Throttle};
+use crate::reader::PeerReader;
+use crate::sendfile::FileSource;
+use crate::sniff::SniffedStream;
+use crate::state::{PeerFlags, PeerState};
+use crate::stats::{PeerCounters, PeerStats};
+use crate::stream::stream_any;
 use aqueue::Actor;
 use std::io::ErrorKind;
-use std::net::SocketAddr;
 use std::ops::Deref;
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 use tokio::io::WriteHalf;
 use tokio::io::{AsyncRead, AsyncWrite, AsyncWriteExt};
 
+/// 设置了发送限速时 send_file 每次发送的块大小
+const SEND_FILE_CHUNK: u64 = 64 * 1024;
+
+/// kick 发送告别消息和关闭连接的最长时间
+const KICK_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// TCP 连接句柄,地址和统计等不变的数据放在 actor 外面,发送经过 actor 排队
 pub struct TCPPeer<T> {
-    pub addr: SocketAddr,
-    pub sender: Option<WriteHalf<T>>,
+    addr: PeerAddr,
+    stats: Arc<PeerCounters>,
+    throttle: Arc<Throttle>,
+    flags: Arc<PeerFlags>,
+    /// 发送顺序锁,限速等待和分块发送文件时不占用 actor
+    sending: tokio::sync::Mutex<()>,
+    inner: Actor<TCPPeerInner<T>>,
+}
+
+/// actor 中保存的可变状态
+pub(crate) struct TCPPeerInner<T> {
+    sender: Option<WriteHalf<T>>,
+    extensions: Extensions,
+    stats: Arc<PeerCounters>,
+    flags: Arc<PeerFlags>,
+    #[cfg(target_os = "linux")]
+    sendfile_fd: Option<std::os::unix::io::RawFd>,
 }
 
 impl<T> TCPPeer<T>
@@ -18,23 +50,163 @@ where
 {
     /// 创建一个TCP PEER
     #[inline]
-    pub fn new(addr: SocketAddr, sender: WriteHalf<T>) -> Arc<Actor<TCPPeer<T>>> {
-        Arc::new(Actor::new(TCPPeer {
+    pub fn new<A: Into<PeerAddr>>(addr: A, sender: WriteHalf<T>) -> Arc<TCPPeer<T>> {
+        Self::with_inner(
+            addr.into(),
+            sender,
+            Extensions::new(),
+            Default::default(),
+            #[cfg(target_os = "linux")]
+            None,
+        )
+    }
+
+    #[inline]
+    fn with_inner(
+        addr: PeerAddr,
+        sender: WriteHalf<T>,
+        extensions: Extensions,
+        throttle: Throttle,
+        #[cfg(target_os = "linux")] sendfile_fd: Option<std::os::unix::io::RawFd>,
+    ) -> Arc<TCPPeer<T>> {
+        let stats = Arc::new(PeerCounters::new());
+        let throttle = Arc::new(throttle);
+        let flags = Arc::new(PeerFlags::default());
+        Arc::new(TCPPeer {
             addr,
-            sender: Some(sender),
-        }))
+            stats: stats.clone(),
+            throttle,
+            flags: flags.clone(),
+            sending: tokio::sync::Mutex::new(()),
+            inner: Actor::new(TCPPeerInner {
+                sender: Some(sender),
+                extensions,
+                stats,
+                flags,
+                #[cfg(target_os = "linux")]
+                sendfile_fd,
+            }),
+        })
+    }
+
+    /// 拆分流并创建TCP PEER,如果是原始 TcpStream/UnixStream(包括装箱的)记录 fd 以便 sendfile,
+    /// 如果是 SniffedStream 把探测到的协议保存到扩展数据
+    #[inline]
+    pub(crate) fn from_stream(
+        addr: PeerAddr,
+        stream: T,
+        throttle: Throttle,
+    ) -> (PeerReader<T>, Arc<TCPPeer<T>>) {
+        #[cfg(target_os = "linux")]
+        let sendfile_fd = {
+            use std::os::unix::io::AsRawFd;
+            let stream = stream_any(&stream);
+            stream
+                .downcast_ref::<tokio::net::TcpStream>()
+                .map(|stream| stream.as_raw_fd())
+                .or_else(|| {
+                    stream
+                        .downcast_ref::<tokio::net::UnixStream>()
+                        .map(|stream| stream.as_raw_fd())
+                })
+                .or_else(|| {
+                    stream
+                        .downcast_ref::<SniffedStream>()
+                        .and_then(SniffedStream::as_plain)
+                        .map(|stream| stream.as_raw_fd())
+                })
+        };
+        let mut extensions = Extensions::new();
+        if let Some(stream) = stream_any(&stream).downcast_ref::<SniffedStream>() {
+            extensions.insert(stream.protocol());
+        }
+        let (reader, sender) = tokio::io::split(stream);
+        let peer = Self::with_inner(
+            addr,
+            sender,
+            extensions,
+            throttle,
+            #[cfg(target_os = "linux")]
+            sendfile_fd,
+        );
+        let reader = PeerReader::new(
+            reader,
+            peer.stats.clone(),
+            peer.throttle.clone(),
+            peer.flags.clone(),
+        );
+        (reader, peer)
+    }
+
+    /// 是否调用过 disconnect 或者 kick,用于判断断线原因
+    #[inline]
+    pub(crate) fn is_closed_by_server(&self) -> bool {
+        self.flags.is_disconnected()
     }
-    /// 是否断线
+
+    /// kick 的原因
     #[inline]
-    pub fn is_disconnect(&self) -> bool {
-        self.sender.is_none()
+    pub(crate) fn take_kick_reason(&self) -> Option<String> {
+        self.flags.take_kick_reason()
+    }
+
+    /// 执行发送,kick 时放弃排队中或者阻塞中的发送,让出 actor
+    #[inline]
+    async fn abort_on_kick<R>(
+        &self,
+        send: impl std::future::Future<Output = Result<R>>,
+    ) -> Result<R> {
+        tokio::select! {
+            result = send => result,
+            _ = self.flags.wait_kicked() => {
+                Err(std::io::Error::new(ErrorKind::ConnectionAborted, "kicked").into())
+            }
+        }
+    }
+
+    /// 按发送限速等待,在 actor 外面等待,不阻塞 disconnect/kick/ext
+    #[inline]
+    async fn wait_egress(&self, len: u64) {
+        let wait = self.throttle.egress(len);
+        if !wait.is_zero() {
+            tokio::time::sleep(wait).await;
+        }
+    }
+}
+
+impl<T> TCPPeerInner<T>
+where
+    T: AsyncRead + AsyncWrite + Send + 'static,
+{
+    /// 记录发送字节
+    #[inline]
+    fn sent(&self, len: u64) {
+        self.stats.sent(len);
+        instrument::bytes_sent(len);
+    }
+
+    /// 记录一条完整发送的消息,每次 send/send_all 调用最多计一次
+    #[inline]
+    fn message_sent(&self, start: Instant) {
+        self.stats.message();
+        instrument::message_sent(start.elapsed());
     }
 
     /// 发送
     #[inline]
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "trace", skip_all, fields(len = buff.len()))
+    )]
     pub async fn send<'a>(&'a mut self, buff: &'a [u8]) -> Result<usize> {
         if let Some(ref mut sender) = self.sender {
-            Ok(sender.write(buff).await?)
+            let start = Instant::now();
+            let size = sender.write(buff).await?;
+            self.sent(size as u64);
+            if size == buff.len() {
+                self.message_sent(start);
+            }
+            Ok(size)
         } else {
             Err(std::io::Error::from(ErrorKind::ConnectionReset).into())
         }
@@ -42,10 +214,17 @@ where
 
     /// 发送全部
     #[inline]
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "trace", skip_all, fields(len = buff.len()))
+    )]
     pub async fn send_all<'a>(&'a mut self, buff: &'a [u8]) -> Result<()> {
         if let Some(ref mut sender) = self.sender {
+            let start = Instant::now();
             sender.write_all(buff).await?;
             sender.flush().await?;
+            self.sent(buff.len() as u64);
+            self.message_sent(start);
             Ok(())
         } else {
             Err(std::io::Error::from(ErrorKind::ConnectionReset).into())
@@ -63,9 +242,70 @@ where
         }
     }
 
+    /// 发送文件的一段,从 offset 开始最多发送 len 字节,返回实际发送的字节数,
+    /// 只统计字节,消息数由调用方在整个文件发送完后统计。
+    /// 原始 TcpStream 在 linux 下使用 sendfile,其他流使用缓冲复制
+    #[inline]
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "trace", skip_all, fields(offset = offset, len = len))
+    )]
+    pub async fn send_file(&mut self, file: &std::fs::File, offset: u64, len: u64) -> Result<u64> {
+        if let Some(ref mut sender) = self.sender {
+            #[cfg(target_os = "linux")]
+            if let Some(fd) = self.sendfile_fd {
+                sender.flush().await?;
+                let size = crate::sendfile::sendfile(fd, file, offset, len).await?;
+                self.sent(size);
+                return Ok(size);
+            }
+            let size = crate::sendfile::copy_file(sender, file, offset, len).await?;
+            self.sent(size);
+            Ok(size)
+        } else {
+            Err(std::io::Error::from(ErrorKind::ConnectionReset).into())
+        }
+    }
+
+    /// 关闭发送方向(发送 FIN),仍然可以继续读取
+    #[inline]
+    pub async fn shutdown_write(&mut self) -> Result<()> {
+        self.flags.write_closed();
+        if let Some(mut sender) = self.sender.take() {
+            Ok(sender.shutdown().await?)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// 限时发送告别消息后关闭读写两个方向,调用前已经设置 kick 标记并唤醒读取
+    pub async fn kick(&mut self, farewell: &[u8]) -> Result<()> {
+        let deadline = tokio::time::Instant::now() + KICK_TIMEOUT;
+        let result = if farewell.is_empty() {
+            Ok(())
+        } else {
+            match tokio::time::timeout_at(deadline, self.send_all(farewell)).await {
+                Ok(result) => result,
+                Err(_) => Err(std::io::Error::from(ErrorKind::TimedOut).into()),
+            }
+        };
+        if let Some(mut sender) = self.sender.take() {
+            let _ = tokio::time::timeout_at(deadline, sender.shutdown()).await;
+            // 还持有写端,fd 仍然有效
+            #[cfg(target_os = "linux")]
+            if let Some(fd) = self.sendfile_fd {
+                unsafe {
+                    libc::shutdown(fd, libc::SHUT_RDWR);
+                }
+            }
+        }
+        result
+    }
+
     /// 掐线
     #[inline]
     pub async fn disconnect(&mut self) -> Result<()> {
+        self.flags.disconnected();
         if let Some(mut sender) = self.sender.take() {
             Ok(sender.shutdown().await?)
         } else {
@@ -75,7 +315,11 @@ where
 }
 
 pub trait IPeer: Sync + Send {
-    fn addr(&self) -> SocketAddr;
+    fn addr(&self) -> PeerAddr;
+    fn stats(&self) -> PeerStats;
+    fn set_ingress_limit(&self, limit: RateLimit);
+    fn set_egress_limit(&self, limit: RateLimit);
+    fn state(&self) -> PeerState;
     fn is_disconnect(&self) -> impl std::future::Future<Output = Result<bool>>;
     fn send<B: Deref<Target = [u8]> + Send + Sync + 'static>(
         &self,
@@ -88,22 +332,62 @@ pub trait IPeer: Sync + Send {
     fn send_ref(&self, buff: &[u8]) -> impl std::future::Future<Output = Result<usize>>;
     fn send_all_ref(&self, buff: &[u8]) -> impl std::future::Future<Output = Result<()>>;
     fn flush(&self) -> impl std::future::Future<Output = Result<()>>;
+    fn send_file<F: Into<FileSource> + Send>(
+        &self,
+        file: F,
+        offset: u64,
+        len: u64,
+    ) -> impl std::future::Future<Output = Result<u64>>;
+    fn shutdown_write(&self) -> impl std::future::Future<Output = Result<()>>;
     fn disconnect(&self) -> impl std::future::Future<Output = Result<()>>;
+    fn kick<S: Into<String>>(
+        &self,
+        reason: S,
+        farewell: &[u8],
+    ) -> impl std::future::Future<Output = Result<()>>;
+    fn ext<E: Clone + Send + Sync + 'static>(&self)
+        -> impl std::future::Future<Output = Option<E>>;
+    fn set_ext<E: Send + Sync + 'static>(
+        &self,
+        val: E,
+    ) -> impl std::future::Future<Output = Option<E>>;
+    fn remove_ext<E: Send + Sync + 'static>(&self) -> impl std::future::Future<Output = Option<E>>;
 }
 
-impl<T> IPeer for Actor<TCPPeer<T>>
+impl<T> IPeer for TCPPeer<T>
 where
     T: AsyncRead + AsyncWrite + Send + 'static,
 {
     #[inline]
-    fn addr(&self) -> SocketAddr {
-        unsafe { self.deref_inner().addr }
+    fn addr(&self) -> PeerAddr {
+        self.addr.clone()
+    }
+
+    #[inline]
+    fn stats(&self) -> PeerStats {
+        self.stats.snapshot()
     }
 
+    #[inline]
+    fn set_ingress_limit(&self, limit: RateLimit) {
+        self.throttle.set_ingress(limit)
+    }
+
+    #[inline]
+    fn set_egress_limit(&self, limit: RateLimit) {
+        self.throttle.set_egress(limit)
+    }
+
+    /// 连接状态,不经过 actor 排队
+    #[inline]
+    fn state(&self) -> PeerState {
+        self.flags.state()
+    }
+
+    /// 是否完全断开,半关闭时返回 false,具体状态见 state()
     #[inline]
     async fn is_disconnect(&self) -> Result<bool> {
-        self.inner_call(|inner| async move { Ok(inner.get().is_disconnect()) })
-            .await
+        Ok(self.flags.state() == PeerState::Closed)
     }
 
     #[inline]
@@ -111,37 +395,152 @@ where
         &self,
         buff: B,
     ) -> Result<usize> {
-        self.inner_call(|inner| async move { inner.get_mut().send(&buff).await })
-            .await
+        let _queued = self.stats.enqueue();
+        self.abort_on_kick(async {
+            let _sending = self.sending.lock().await;
+            self.wait_egress(buff.len() as u64).await;
+            self.inner
+                .inner_call(|inner| async move { inner.get_mut().send(&buff).await })
+                .await
+        })
+        .await
     }
     #[inline]
     async fn send_all<B: Deref<Target = [u8]> + Send + Sync + 'static>(
         &self,
         buff: B,
     ) -> Result<()> {
-        self.inner_call(|inner| async move { inner.get_mut().send_all(&buff).await })
-            .await
+        let _queued = self.stats.enqueue();
+        self.abort_on_kick(async {
+            let _sending = self.sending.lock().await;
+            self.wait_egress(buff.len() as u64).await;
+            self.inner
+                .inner_call(|inner| async move { inner.get_mut().send_all(&buff).await })
+                .await
+        })
+        .await
     }
     #[inline]
     async fn send_ref(&self, buff: &[u8]) -> Result<usize> {
-        self.inner_call(|inner| async move { inner.get_mut().send(buff).await })
-            .await
+        let _queued = self.stats.enqueue();
+        self.abort_on_kick(async {
+            let _sending = self.sending.lock().await;
+            self.wait_egress(buff.len() as u64).await;
+            self.inner
+                .inner_call(|inner| async move { inner.get_mut().send(buff).await })
+                .await
+        })
+        .await
     }
     #[inline]
     async fn send_all_ref(&self, buff: &[u8]) -> Result<()> {
-        self.inner_call(|inner| async move { inner.get_mut().send_all(buff).await })
-            .await
+        let _queued = self.stats.enqueue();
+        self.abort_on_kick(async {
+            let _sending = self.sending.lock().await;
+            self.wait_egress(buff.len() as u64).await;
+            self.inner
+                .inner_call(|inner| async move { inner.get_mut().send_all(buff).await })
+                .await
+        })
+        .await
     }
 
     #[inline]
     async fn flush(&self) -> Result<()> {
-        self.inner_call(|inner| async move { inner.get_mut().flush().await })
-            .await
+        self.abort_on_kick(
+            self.inner
+                .inner_call(|inner| async move { inner.get_mut().flush().await }),
+        )
+        .await
+    }
+
+    #[inline]
+    async fn send_file<F: Into<FileSource> + Send>(
+        &self,
+        file: F,
+        offset: u64,
+        len: u64,
+    ) -> Result<u64> {
+        let file = file.into().open().await?;
+        let _queued = self.stats.enqueue();
+        self.abort_on_kick(async {
+            let _sending = self.sending.lock().await;
+            let start = Instant::now();
+            // 限速时分块发送,每块发送前在 actor 外面等待
+            let chunk = if self.throttle.has_egress() {
+                SEND_FILE_CHUNK
+            } else {
+                len.max(1)
+            };
+            let mut total = 0;
+            loop {
+                let size = (len - total).min(chunk);
+                self.wait_egress(size).await;
+                let file = &file;
+                let offset = offset + total;
+                let sent = self
+                    .inner
+                    .inner_call(|inner| async move {
+                        inner.get_mut().send_file(file, offset, size).await
+                    })
+                    .await?;
+                total += sent;
+                // 文件已读完
+                if total >= len || sent < size {
+                    break;
+                }
+            }
+            self.stats.message();
+            instrument::message_sent(start.elapsed());
+            Ok(total)
+        })
+        .await
+    }
+
+    #[inline]
+    async fn shutdown_write(&self) -> Result<()> {
+        self.abort_on_kick(
+            self.inner
+                .inner_call(|inner| async move { inner.get_mut().shutdown_write().await }),
+        )
+        .await
     }
 
     #[inline]
     async fn disconnect(&self) -> Result<()> {
-        self.inner_call(|inner| async move { inner.get_mut().disconnect().await })
+        self.inner
+            .inner_call(|inner| async move { inner.get_mut().disconnect().await })
+            .await
+    }
+
+    /// 踢掉连接,先唤醒读取并放弃正在进行的发送,farewell 不为空时最多等待 1 秒发送,
+    /// 断线事件收到 DisconnectReason::Kicked(Some(reason))
+    #[inline]
+    async fn kick<S: Into<String>>(&self, reason: S, farewell: &[u8]) -> Result<()> {
+        self.flags.kicked(reason.into());
+        self.inner
+            .inner_call(|inner| async move { inner.get_mut().kick(farewell).await })
+            .await
+    }
+
+    #[inline]
+    async fn ext<E: Clone + Send + Sync + 'static>(&self) -> Option<E> {
+        self.inner
+            .inner_call(|inner| async move { inner.get().extensions.get::<E>().cloned() })
+            .await
+    }
+
+    #[inline]
+    async fn set_ext<E: Send + Sync + 'static>(&self, val: E) -> Option<E> {
+        self.inner
+            .inner_call(|inner| async move { inner.get_mut().extensions.insert(val) })
+            .await
+    }
+
+    #[inline]
+    async fn remove_ext<E: Send + Sync + 'static>(&self) -> Option<E> {
+        self.inner
+            .inner_call(|inner| async move { inner.get_mut().extensions.remove::<E>() })
             .await
     }
 }
@@ -0,0 +1,182 @@
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, Instant};
+
+/// 限速配置,None 表示不限制
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub struct RateLimit {
+    /// 每秒字节数
+    pub bytes_per_sec: Option<u64>,
+    /// 每秒消息数,发送方向按 send/send_all/send_file 调用计数,
+    /// 接收方向按读取次数(reads/s)计数,一次读取可能包含多条消息也可能只有半条
+    pub messages_per_sec: Option<u64>,
+}
+
+impl RateLimit {
+    /// 按字节限速
+    #[inline]
+    pub fn bytes(bytes_per_sec: u64) -> Self {
+        RateLimit {
+            bytes_per_sec: Some(bytes_per_sec),
+            messages_per_sec: None,
+        }
+    }
+
+    /// 按消息数限速,接收方向为每秒读取次数
+    #[inline]
+    pub fn messages(messages_per_sec: u64) -> Self {
+        RateLimit {
+            bytes_per_sec: None,
+            messages_per_sec: Some(messages_per_sec),
+        }
+    }
+
+    /// 同时按消息数限速
+    #[inline]
+    pub fn with_messages(mut self, messages_per_sec: u64) -> Self {
+        self.messages_per_sec = Some(messages_per_sec);
+        self
+    }
+
+    /// 同时按字节限速
+    #[inline]
+    pub fn with_bytes(mut self, bytes_per_sec: u64) -> Self {
+        self.bytes_per_sec = Some(bytes_per_sec);
+        self
+    }
+}
+
+/// 令牌桶,容量为一秒的速率,允许透支,透支部分按速率计算等待时间
+struct TokenBucket {
+    rate: f64,
+    state: Mutex<(f64, Instant)>,
+}
+
+impl TokenBucket {
+    fn new(rate: u64) -> Self {
+        let rate = rate.max(1) as f64;
+        TokenBucket {
+            rate,
+            state: Mutex::new((rate, Instant::now())),
+        }
+    }
+
+    fn reserve(&self, amount: u64) -> Duration {
+        let mut state = self.state.lock().unwrap();
+        let now = Instant::now();
+        let (ref mut tokens, ref mut last) = *state;
+        *tokens = (*tokens + now.duration_since(*last).as_secs_f64() * self.rate).min(self.rate);
+        *last = now;
+        *tokens -= amount as f64;
+        if *tokens >= 0.0 {
+            Duration::ZERO
+        } else {
+            Duration::from_secs_f64(-*tokens / self.rate)
+        }
+    }
+}
+
+/// 字节和消息两个令牌桶
+pub(crate) struct Limiter {
+    bytes: Option<TokenBucket>,
+    messages: Option<TokenBucket>,
+}
+
+impl Limiter {
+    pub(crate) fn new(limit: RateLimit) -> Option<Arc<Limiter>> {
+        if limit == RateLimit::default() {
+            return None;
+        }
+        Some(Arc::new(Limiter {
+            bytes: limit.bytes_per_sec.map(TokenBucket::new),
+            messages: limit.messages_per_sec.map(TokenBucket::new),
+        }))
+    }
+
+    /// 申请发送或接收 len 字节的一条消息,返回需要等待的时间
+    fn acquire(&self, len: u64) -> Duration {
+        let bytes = self
+            .bytes
+            .as_ref()
+            .map_or(Duration::ZERO, |bucket| bucket.reserve(len));
+        let messages = self
+            .messages
+            .as_ref()
+            .map_or(Duration::ZERO, |bucket| bucket.reserve(1));
+        bytes.max(messages)
+    }
+}
+
+/// 服务器级别的限速配置
+#[derive(Clone, Default)]
+pub(crate) struct ServerLimits {
+    pub(crate) global_ingress: Option<Arc<Limiter>>,
+    pub(crate) global_egress: Option<Arc<Limiter>>,
+    pub(crate) peer_ingress: RateLimit,
+    pub(crate) peer_egress: RateLimit,
+}
+
+/// 单个连接的限速器,由 TCPPeer 和 PeerReader 共享
+#[derive(Default)]
+pub(crate) struct Throttle {
+    global_ingress: Option<Arc<Limiter>>,
+    global_egress: Option<Arc<Limiter>>,
+    ingress: RwLock<Option<Arc<Limiter>>>,
+    egress: RwLock<Option<Arc<Limiter>>>,
+}
+
+impl Throttle {
+    pub(crate) fn new(limits: &ServerLimits) -> Self {
+        Throttle {
+            global_ingress: limits.global_ingress.clone(),
+            global_egress: limits.global_egress.clone(),
+            ingress: RwLock::new(Limiter::new(limits.peer_ingress)),
+            egress: RwLock::new(Limiter::new(limits.peer_egress)),
+        }
+    }
+
+    /// 替换本连接的接收限速
+    #[inline]
+    pub(crate) fn set_ingress(&self, limit: RateLimit) {
+        *self.ingress.write().unwrap() = Limiter::new(limit);
+    }
+
+    /// 替换本连接的发送限速
+    #[inline]
+    pub(crate) fn set_egress(&self, limit: RateLimit) {
+        *self.egress.write().unwrap() = Limiter::new(limit);
+    }
+
+    /// 接收了 len 字节,返回下次读取前需要等待的时间
+    #[inline]
+    pub(crate) fn ingress(&self, len: u64) -> Duration {
+        Self::acquire(&self.global_ingress, &self.ingress, len)
+    }
+
+    /// 将要发送 len 字节,返回发送前需要等待的时间
+    #[inline]
+    pub(crate) fn egress(&self, len: u64) -> Duration {
+        Self::acquire(&self.global_egress, &self.egress, len)
+    }
+
+    /// 是否设置了发送限速
+    #[inline]
+    pub(crate) fn has_egress(&self) -> bool {
+        self.global_egress.is_some() || self.egress.read().unwrap().is_some()
+    }
+
+    fn acquire(
+        global: &Option<Arc<Limiter>>,
+        peer: &RwLock<Option<Arc<Limiter>>>,
+        len: u64,
+    ) -> Duration {
+        let global = global
+            .as_ref()
+            .map_or(Duration::ZERO, |limiter| limiter.acquire(len));
+        let peer = peer
+            .read()
+            .unwrap()
+            .as_ref()
+            .map_or(Duration::ZERO, |limiter| limiter.acquire(len));
+        global.max(peer)
+    }
+}
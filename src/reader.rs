@@ -0,0 +1,100 @@
+use crate::instrument;
+use crate::ratelimit::Throttle;
+use crate::state::PeerFlags;
+use crate::stats::PeerCounters;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use tokio::io::{AsyncRead, ReadBuf, ReadHalf};
+use tokio::time::Sleep;
+
+/// 框架包装的读取端,传给 input_event,代替原来的 ReadHalf。
+/// 通过它统计读取字节、限速、记录半关闭状态以及响应 kick
+pub struct PeerReader<C> {
+    inner: ReadHalf<C>,
+    stats: Arc<PeerCounters>,
+    throttle: Arc<Throttle>,
+    flags: Arc<PeerFlags>,
+    delay: Option<Pin<Box<Sleep>>>,
+}
+
+impl<C> PeerReader<C> {
+    #[inline]
+    pub(crate) fn new(
+        inner: ReadHalf<C>,
+        stats: Arc<PeerCounters>,
+        throttle: Arc<Throttle>,
+        flags: Arc<PeerFlags>,
+    ) -> Self {
+        PeerReader {
+            inner,
+            stats,
+            throttle,
+            flags,
+            delay: None,
+        }
+    }
+
+    /// 取出原始的 ReadHalf,之后的读取不再统计、限速和响应 kick
+    #[inline]
+    pub fn into_inner(self) -> ReadHalf<C> {
+        self.inner
+    }
+}
+
+/// 连接被 kick 后读取返回的错误
+#[inline]
+fn kicked() -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::ConnectionAborted, "kicked")
+}
+
+impl<C: AsyncRead> AsyncRead for PeerReader<C> {
+    #[inline]
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<std::io::Result<()>> {
+        if self.flags.is_kicked() {
+            return Poll::Ready(Err(kicked()));
+        }
+        if let Some(delay) = self.delay.as_mut() {
+            if delay.as_mut().poll(cx).is_pending() {
+                self.flags.register_reader(cx.waker());
+                return Poll::Pending;
+            }
+            self.delay = None;
+        }
+        let before = buf.filled().len();
+        let poll = Pin::new(&mut self.inner).poll_read(cx, buf);
+        if poll.is_pending() {
+            self.flags.register_reader(cx.waker());
+            // 登记之前可能已经被 kick
+            if self.flags.is_kicked() {
+                return Poll::Ready(Err(kicked()));
+            }
+            return Poll::Pending;
+        }
+        if let Poll::Ready(Err(_)) = poll {
+            self.flags.read_closed();
+        }
+        if let Poll::Ready(Ok(())) = poll {
+            let len = buf.filled().len() - before;
+            if len == 0 && buf.remaining() > 0 {
+                // EOF
+                self.flags.read_closed();
+            }
+            self.stats.received(len);
+            instrument::bytes_received(len);
+            if len > 0 {
+                // 超出接收限速时推迟下一次读取
+                let wait = self.throttle.ingress(len as u64);
+                if !wait.is_zero() {
+                    self.delay = Some(Box::pin(tokio::time::sleep(wait)));
+                }
+            }
+        }
+        poll
+    }
+}
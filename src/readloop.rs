@@ -0,0 +1,124 @@
+use crate::error::Error;
+use crate::handler::ConnectionHandler;
+use crate::listener::PeerAddr;
+use crate::peer::TCPPeer;
+use crate::reader::PeerReader;
+use crate::tcpserver::DisconnectReason;
+use bytes::BytesMut;
+use std::future::Future;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite};
+
+/// 由框架驱动读取的处理器,类似 netty 的 ByteToMessageDecoder
+pub trait ReadHandler<C, T>: Send + Sync + 'static {
+    /// 新连接,返回 false 拒绝连接
+    #[inline]
+    fn on_connect(&self, _addr: &PeerAddr) -> bool {
+        true
+    }
+
+    /// 收到数据,消费能解析的部分,剩余的数据保留到下次读取
+    fn on_read(
+        &self,
+        peer: &Arc<TCPPeer<C>>,
+        buff: &mut BytesMut,
+        token: &T,
+    ) -> impl Future<Output = anyhow::Result<()>> + Send;
+
+    /// 断线,每个连接只会触发一次
+    #[inline]
+    fn on_disconnect(
+        &self,
+        _peer: Arc<TCPPeer<C>>,
+        _reason: DisconnectReason,
+        _token: T,
+    ) -> impl Future<Output = ()> + Send {
+        async {}
+    }
+}
+
+/// 把 ReadHandler 包装成 ConnectionHandler,通过 Builder::with_handler 使用
+pub struct ReadLoop<H> {
+    handler: H,
+    capacity: usize,
+    max_buffer: usize,
+    idle_timeout: Option<Duration>,
+}
+
+impl<H> ReadLoop<H> {
+    /// 默认每次至少预留 4K,缓冲最大 1M,不限空闲时间
+    pub fn new(handler: H) -> Self {
+        ReadLoop {
+            handler,
+            capacity: 4096,
+            max_buffer: 1024 * 1024,
+            idle_timeout: None,
+        }
+    }
+
+    /// 每次读取前至少预留的缓冲大小
+    pub fn set_capacity(mut self, capacity: usize) -> Self {
+        self.capacity = capacity.max(1);
+        self
+    }
+
+    /// 未消费数据的上限,超过后断开连接
+    pub fn set_max_buffer(mut self, max_buffer: usize) -> Self {
+        self.max_buffer = max_buffer;
+        self
+    }
+
+    /// 超过时间没有收到数据断开连接,断线原因为 IdleTimeout
+    pub fn set_idle_timeout(mut self, timeout: Duration) -> Self {
+        self.idle_timeout = Some(timeout);
+        self
+    }
+}
+
+impl<H, C, T> ConnectionHandler<C, T> for ReadLoop<H>
+where
+    H: ReadHandler<C, T>,
+    C: AsyncRead + AsyncWrite + Send + 'static,
+    T: Send + Sync + 'static,
+{
+    #[inline]
+    fn on_connect(&self, addr: &PeerAddr) -> bool {
+        self.handler.on_connect(addr)
+    }
+
+    async fn handle(
+        &self,
+        mut reader: PeerReader<C>,
+        peer: Arc<TCPPeer<C>>,
+        token: T,
+    ) -> anyhow::Result<()> {
+        let mut buff = BytesMut::with_capacity(self.capacity);
+        loop {
+            if buff.len() >= self.max_buffer {
+                return Err(Error::ReadBufferFull(self.max_buffer).into());
+            }
+            buff.reserve(self.capacity);
+            let len = match self.idle_timeout {
+                Some(timeout) => tokio::time::timeout(timeout, reader.read_buf(&mut buff))
+                    .await
+                    .map_err(|_| Error::IdleTimeout)??,
+                None => reader.read_buf(&mut buff).await?,
+            };
+            if len == 0 {
+                return Ok(());
+            }
+            self.handler.on_read(&peer, &mut buff, &token).await?;
+        }
+    }
+
+    #[inline]
+    fn on_disconnect(
+        &self,
+        peer: Arc<TCPPeer<C>>,
+        reason: DisconnectReason,
+        token: T,
+    ) -> impl Future<Output = ()> + Send {
+        self.handler.on_disconnect(peer, reason, token)
+    }
+}
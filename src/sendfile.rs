@@ -0,0 +1,152 @@
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tokio::io::{AsyncRead, AsyncWrite, AsyncWriteExt, WriteHalf};
+
+/// 用户态复制时每次读取的大小
+const COPY_BUFFER_SIZE: usize = 64 * 1024;
+
+/// send_file 的文件来源,可以是路径或者已打开的文件
+pub enum FileSource {
+    Path(PathBuf),
+    File(File),
+}
+
+impl FileSource {
+    /// 打开文件
+    pub(crate) async fn open(self) -> std::io::Result<File> {
+        match self {
+            FileSource::Path(path) => Ok(tokio::fs::File::open(path).await?.into_std().await),
+            FileSource::File(file) => Ok(file),
+        }
+    }
+}
+
+impl From<File> for FileSource {
+    #[inline]
+    fn from(file: File) -> Self {
+        FileSource::File(file)
+    }
+}
+
+impl From<PathBuf> for FileSource {
+    #[inline]
+    fn from(path: PathBuf) -> Self {
+        FileSource::Path(path)
+    }
+}
+
+impl From<&Path> for FileSource {
+    #[inline]
+    fn from(path: &Path) -> Self {
+        FileSource::Path(path.to_path_buf())
+    }
+}
+
+impl From<&str> for FileSource {
+    #[inline]
+    fn from(path: &str) -> Self {
+        FileSource::Path(path.into())
+    }
+}
+
+impl From<String> for FileSource {
+    #[inline]
+    fn from(path: String) -> Self {
+        FileSource::Path(path.into())
+    }
+}
+
+/// 通过用户态缓冲复制文件,用于TLS等包装过的流,
+/// 按位置读取,和 sendfile 一样不移动文件的读写位置(Windows 除外)
+pub(crate) async fn copy_file<T>(
+    sender: &mut WriteHalf<T>,
+    file: &File,
+    offset: u64,
+    len: u64,
+) -> std::io::Result<u64>
+where
+    T: AsyncRead + AsyncWrite,
+{
+    let file = Arc::new(file.try_clone()?);
+    let mut buff = vec![0; COPY_BUFFER_SIZE];
+    let mut total = 0u64;
+    while total < len {
+        let count = (len - total).min(COPY_BUFFER_SIZE as u64) as usize;
+        let file = file.clone();
+        let position = offset + total;
+        let (read, back) = tokio::task::spawn_blocking(move || {
+            let read = read_at(&file, &mut buff[..count], position);
+            (read, buff)
+        })
+        .await?;
+        buff = back;
+        let size = read?;
+        // 文件已读完
+        if size == 0 {
+            break;
+        }
+        sender.write_all(&buff[..size]).await?;
+        total += size as u64;
+    }
+    sender.flush().await?;
+    Ok(total)
+}
+
+#[cfg(unix)]
+#[inline]
+fn read_at(file: &File, buf: &mut [u8], offset: u64) -> std::io::Result<usize> {
+    std::os::unix::fs::FileExt::read_at(file, buf, offset)
+}
+
+/// Windows 上 seek_read 会移动文件的读写位置
+#[cfg(windows)]
+#[inline]
+fn read_at(file: &File, buf: &mut [u8], offset: u64) -> std::io::Result<usize> {
+    std::os::windows::fs::FileExt::seek_read(file, buf, offset)
+}
+
+/// 使用 sendfile 零拷贝发送文件,fd 复制一份注册到 tokio,写满时等待可写,
+/// future 被丢弃时立即停止
+#[cfg(target_os = "linux")]
+pub(crate) async fn sendfile(
+    fd: std::os::unix::io::RawFd,
+    file: &File,
+    offset: u64,
+    len: u64,
+) -> std::io::Result<u64> {
+    use std::os::unix::io::{AsRawFd, BorrowedFd};
+    use tokio::io::unix::AsyncFd;
+    use tokio::io::Interest;
+
+    // 调用方持有写端,fd 在这里仍然有效,复制之后不受原连接关闭影响
+    let socket = unsafe { BorrowedFd::borrow_raw(fd) }.try_clone_to_owned()?;
+    // SAFETY: socket 是自己持有的 OwnedFd,在 AsyncFd 释放之前一直有效
+    let socket = unsafe { AsyncFd::register_with_interest(socket, Interest::WRITABLE) }
+        .map_err(|err| err.into_parts().1)?;
+    let mut off = offset as libc::off_t;
+    let mut total = 0u64;
+    while total < len {
+        let count = (len - total).min(0x7fff_f000) as usize;
+        let mut guard = socket.writable().await?;
+        let sent = guard.try_io(|socket| {
+            let ret =
+                unsafe { libc::sendfile(socket.as_raw_fd(), file.as_raw_fd(), &mut off, count) };
+            if ret < 0 {
+                Err(std::io::Error::last_os_error())
+            } else {
+                Ok(ret as u64)
+            }
+        });
+        match sent {
+            // 文件已读完
+            Ok(Ok(0)) => break,
+            Ok(Ok(size)) => total += size,
+            Ok(Err(err)) if err.kind() == std::io::ErrorKind::Interrupted => {}
+            Ok(Err(err)) => return Err(err),
+            // 写满,清除就绪状态后继续等待
+            Err(_would_block) => {}
+        }
+    }
+    Ok(total)
+}
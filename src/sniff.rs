@@ -0,0 +1,271 @@
+use std::io;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncWrite, Interest, ReadBuf};
+use tokio::net::TcpStream;
+use tokio::time::Instant;
+
+/// TLS 记录头: handshake(0x16) + 版本主号 3
+const TLS_HANDSHAKE: &[u8] = &[0x16, 0x03];
+const PROXY_V1: &[u8] = b"PROXY ";
+const PROXY_V2: &[u8] = b"\r\n\r\n\0\r\nQUIT\n";
+const HTTP_METHODS: &[&[u8]] = &[
+    b"GET ",
+    b"POST ",
+    b"PUT ",
+    b"HEAD ",
+    b"DELETE ",
+    b"OPTIONS ",
+    b"PATCH ",
+    b"CONNECT ",
+    b"TRACE ",
+    b"PRI * HTTP/2",
+];
+
+/// 连接首包探测到的协议,会以扩展数据保存在 peer 上,通过 `peer.ext::<Protocol>()` 获取
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum Protocol {
+    /// TLS ClientHello
+    Tls,
+    /// HTTP 请求行
+    Http,
+    /// PROXY protocol v1 文本头,头部没有被消费
+    ProxyV1,
+    /// PROXY protocol v2 二进制头,头部没有被消费
+    ProxyV2,
+    /// 其他明文协议,或者对端发送首包之前就关闭了发送
+    Plain,
+}
+
+impl Protocol {
+    /// 根据已收到的首包判断协议,数据不足以区分时返回 None
+    pub fn detect(buf: &[u8]) -> Option<Protocol> {
+        let mut partial = false;
+        let mut check = |sig: &[u8]| {
+            if buf.starts_with(sig) {
+                true
+            } else {
+                partial |= sig.starts_with(buf);
+                false
+            }
+        };
+        if check(TLS_HANDSHAKE) {
+            return Some(Protocol::Tls);
+        }
+        if check(PROXY_V1) {
+            return Some(Protocol::ProxyV1);
+        }
+        if check(PROXY_V2) {
+            return Some(Protocol::ProxyV2);
+        }
+        if HTTP_METHODS.iter().any(|method| check(method)) {
+            return Some(Protocol::Http);
+        }
+        if partial {
+            None
+        } else {
+            Some(Protocol::Plain)
+        }
+    }
+
+    /// 是否为加密连接
+    #[inline]
+    pub fn is_tls(&self) -> bool {
+        matches!(self, Protocol::Tls)
+    }
+}
+
+/// 不消费数据,peek 连接首包探测协议,首包不完整时等待更多数据。
+/// 超时前没有收到可以判断的数据返回 TimedOut 错误,对端没有发送数据就关闭视为 Plain
+pub async fn sniff(stream: &TcpStream, timeout: Duration) -> io::Result<Protocol> {
+    let deadline = Instant::now() + timeout;
+    let timed_out = |_| io::Error::new(io::ErrorKind::TimedOut, "sniff timeout");
+    let mut buf = [0; 16];
+    let mut seen = 0;
+    let mut woke = false;
+    loop {
+        let len = tokio::time::timeout_at(deadline, stream.peek(&mut buf))
+            .await
+            .map_err(timed_out)??;
+        if len == 0 {
+            return Ok(Protocol::Plain);
+        }
+        if let Some(protocol) = Protocol::detect(&buf[..len]) {
+            return Ok(protocol);
+        }
+        if len > seen {
+            // 首包不完整,清除可读状态后再看一次,之后到达的数据会重新触发可读
+            seen = len;
+            woke = false;
+            let _ = stream.try_io(Interest::READABLE, || {
+                Err::<(), _>(io::ErrorKind::WouldBlock.into())
+            });
+            continue;
+        }
+        if woke {
+            // 可读但没有新数据,对端已经关闭发送
+            return Ok(Protocol::Plain);
+        }
+        tokio::time::timeout_at(deadline, stream.readable())
+            .await
+            .map_err(timed_out)??;
+        woke = true;
+    }
+}
+
+/// 在 stream_init 中按首包分流 TLS 和明文连接
+///
+/// ```ignore
+/// let sniffer = Arc::new(Sniffer::new().set_tls_acceptor(acceptor));
+/// Builder::new("0.0.0.0:5555")
+///     .set_stream_init(move |tcp_stream| {
+///         let sniffer = sniffer.clone();
+///         async move { sniffer.accept(tcp_stream).await }
+///     })
+/// ```
+pub struct Sniffer {
+    timeout: Duration,
+    #[cfg(feature = "tls")]
+    acceptor: Option<openssl::ssl::SslAcceptor>,
+}
+
+impl Default for Sniffer {
+    fn default() -> Self {
+        Sniffer {
+            timeout: Duration::from_secs(5),
+            #[cfg(feature = "tls")]
+            acceptor: None,
+        }
+    }
+}
+
+impl Sniffer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 等待首包的时间,超时拒绝连接,默认5秒
+    pub fn set_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// 设置 TLS 证书,没有设置时拒绝 TLS 连接
+    #[cfg(feature = "tls")]
+    pub fn set_tls_acceptor(mut self, acceptor: openssl::ssl::SslAcceptor) -> Self {
+        self.acceptor = Some(acceptor);
+        self
+    }
+
+    /// 探测协议,TLS 连接完成握手
+    pub async fn accept(&self, stream: TcpStream) -> anyhow::Result<SniffedStream> {
+        let protocol = sniff(&stream, self.timeout).await?;
+        if protocol.is_tls() {
+            #[cfg(feature = "tls")]
+            if let Some(ref acceptor) = self.acceptor {
+                let ssl = openssl::ssl::Ssl::new(acceptor.context())?;
+                let mut stream = tokio_openssl::SslStream::new(ssl, stream)?;
+                Pin::new(&mut stream).accept().await?;
+                return Ok(SniffedStream {
+                    protocol,
+                    inner: Inner::Tls(Box::new(stream)),
+                });
+            }
+            anyhow::bail!("tls connection but no tls acceptor");
+        }
+        Ok(SniffedStream {
+            protocol,
+            inner: Inner::Plain(stream),
+        })
+    }
+}
+
+enum Inner {
+    Plain(TcpStream),
+    #[cfg(feature = "tls")]
+    Tls(Box<tokio_openssl::SslStream<TcpStream>>),
+}
+
+/// 探测之后的连接,TLS 已经解密
+pub struct SniffedStream {
+    protocol: Protocol,
+    inner: Inner,
+}
+
+impl SniffedStream {
+    /// 探测到的协议
+    #[inline]
+    pub fn protocol(&self) -> Protocol {
+        self.protocol
+    }
+
+    /// 底层 TcpStream
+    #[inline]
+    pub fn get_ref(&self) -> &TcpStream {
+        match self.inner {
+            Inner::Plain(ref stream) => stream,
+            #[cfg(feature = "tls")]
+            Inner::Tls(ref stream) => stream.get_ref(),
+        }
+    }
+
+    /// 明文连接的 TcpStream,可以直接使用 sendfile
+    #[inline]
+    #[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+    pub(crate) fn as_plain(&self) -> Option<&TcpStream> {
+        match self.inner {
+            Inner::Plain(ref stream) => Some(stream),
+            #[cfg(feature = "tls")]
+            Inner::Tls(_) => None,
+        }
+    }
+}
+
+impl AsyncRead for SniffedStream {
+    #[inline]
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        match self.inner {
+            Inner::Plain(ref mut stream) => Pin::new(stream).poll_read(cx, buf),
+            #[cfg(feature = "tls")]
+            Inner::Tls(ref mut stream) => Pin::new(stream.as_mut()).poll_read(cx, buf),
+        }
+    }
+}
+
+impl AsyncWrite for SniffedStream {
+    #[inline]
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        match self.inner {
+            Inner::Plain(ref mut stream) => Pin::new(stream).poll_write(cx, buf),
+            #[cfg(feature = "tls")]
+            Inner::Tls(ref mut stream) => Pin::new(stream.as_mut()).poll_write(cx, buf),
+        }
+    }
+
+    #[inline]
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        match self.inner {
+            Inner::Plain(ref mut stream) => Pin::new(stream).poll_flush(cx),
+            #[cfg(feature = "tls")]
+            Inner::Tls(ref mut stream) => Pin::new(stream.as_mut()).poll_flush(cx),
+        }
+    }
+
+    #[inline]
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        match self.inner {
+            Inner::Plain(ref mut stream) => Pin::new(stream).poll_shutdown(cx),
+            #[cfg(feature = "tls")]
+            Inner::Tls(ref mut stream) => Pin::new(stream.as_mut()).poll_shutdown(cx),
+        }
+    }
+}
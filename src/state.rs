@@ -0,0 +1,119 @@
+use std::sync::atomic::{AtomicU8, Ordering};
+use std::sync::Mutex;
+use std::task::Waker;
+use tokio::sync::Notify;
+
+const READ_CLOSED: u8 = 1;
+const WRITE_CLOSED: u8 = 2;
+const DISCONNECTED: u8 = 4;
+const KICKED: u8 = 8;
+
+/// 连接状态
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum PeerState {
+    /// 可读可写
+    Open,
+    /// 已经调用 shutdown_write,仍然可以读取
+    WriteClosed,
+    /// 对端已经关闭发送(读到 EOF),仍然可以发送
+    ReadClosed,
+    /// 已经断开
+    Closed,
+}
+
+/// 连接状态标记,由 TCPPeer 和 PeerReader 共享
+#[derive(Default)]
+pub(crate) struct PeerFlags {
+    bits: AtomicU8,
+    kick_reason: Mutex<Option<String>>,
+    /// 挂起中的读取,kick 时唤醒
+    reader: Mutex<Option<Waker>>,
+    /// kick 时通知正在进行的发送放弃
+    kick: Notify,
+}
+
+impl PeerFlags {
+    #[inline]
+    pub(crate) fn read_closed(&self) {
+        self.bits.fetch_or(READ_CLOSED, Ordering::AcqRel);
+    }
+
+    #[inline]
+    pub(crate) fn write_closed(&self) {
+        self.bits.fetch_or(WRITE_CLOSED, Ordering::AcqRel);
+    }
+
+    /// 调用了 disconnect
+    #[inline]
+    pub(crate) fn disconnected(&self) {
+        self.bits
+            .fetch_or(WRITE_CLOSED | DISCONNECTED, Ordering::AcqRel);
+    }
+
+    /// 调用了 kick,保存原因并唤醒挂起的读取
+    pub(crate) fn kicked(&self, reason: String) {
+        *self.kick_reason.lock().unwrap() = Some(reason);
+        self.bits.fetch_or(
+            READ_CLOSED | WRITE_CLOSED | DISCONNECTED | KICKED,
+            Ordering::AcqRel,
+        );
+        if let Some(waker) = self.reader.lock().unwrap().take() {
+            waker.wake();
+        }
+        self.kick.notify_waiters();
+    }
+
+    /// 等待 kick,已经 kick 时立即返回
+    pub(crate) async fn wait_kicked(&self) {
+        loop {
+            let notified = self.kick.notified();
+            tokio::pin!(notified);
+            notified.as_mut().enable();
+            if self.is_kicked() {
+                return;
+            }
+            notified.await;
+        }
+    }
+
+    #[inline]
+    pub(crate) fn is_disconnected(&self) -> bool {
+        self.bits.load(Ordering::Acquire) & DISCONNECTED != 0
+    }
+
+    #[inline]
+    pub(crate) fn is_kicked(&self) -> bool {
+        self.bits.load(Ordering::Acquire) & KICKED != 0
+    }
+
+    #[inline]
+    pub(crate) fn take_kick_reason(&self) -> Option<String> {
+        self.kick_reason.lock().unwrap().take()
+    }
+
+    /// 读取挂起时登记 waker
+    #[inline]
+    pub(crate) fn register_reader(&self, waker: &Waker) {
+        let mut reader = self.reader.lock().unwrap();
+        match *reader {
+            Some(ref old) if old.will_wake(waker) => {}
+            _ => *reader = Some(waker.clone()),
+        }
+    }
+
+    #[inline]
+    pub(crate) fn state(&self) -> PeerState {
+        let flags = self.bits.load(Ordering::Acquire);
+        if flags & DISCONNECTED != 0
+            || flags & (READ_CLOSED | WRITE_CLOSED) == READ_CLOSED | WRITE_CLOSED
+        {
+            PeerState::Closed
+        } else if flags & WRITE_CLOSED != 0 {
+            PeerState::WriteClosed
+        } else if flags & READ_CLOSED != 0 {
+            PeerState::ReadClosed
+        } else {
+            PeerState::Open
+        }
+    }
+}
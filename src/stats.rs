@@ -0,0 +1,108 @@
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::time::{Duration, Instant, SystemTime};
+
+/// 连接流量计数,由 TCPPeer 和 PeerReader 共享
+pub(crate) struct PeerCounters {
+    bytes_sent: AtomicU64,
+    bytes_received: AtomicU64,
+    messages_sent: AtomicU64,
+    queue_depth: AtomicUsize,
+    connected_at: SystemTime,
+    start: Instant,
+    last_active: AtomicU64,
+}
+
+impl PeerCounters {
+    #[inline]
+    pub(crate) fn new() -> Self {
+        PeerCounters {
+            bytes_sent: AtomicU64::new(0),
+            bytes_received: AtomicU64::new(0),
+            messages_sent: AtomicU64::new(0),
+            queue_depth: AtomicUsize::new(0),
+            connected_at: SystemTime::now(),
+            start: Instant::now(),
+            last_active: AtomicU64::new(0),
+        }
+    }
+
+    #[inline]
+    fn touch(&self) {
+        self.last_active
+            .store(self.start.elapsed().as_millis() as u64, Ordering::Relaxed);
+    }
+
+    /// 记录发送字节
+    #[inline]
+    pub(crate) fn sent(&self, len: u64) {
+        if len > 0 {
+            self.bytes_sent.fetch_add(len, Ordering::Relaxed);
+            self.touch();
+        }
+    }
+
+    /// 记录一条完整发送的消息
+    #[inline]
+    pub(crate) fn message(&self) {
+        self.messages_sent.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// 记录读取
+    #[inline]
+    pub(crate) fn received(&self, len: usize) {
+        if len > 0 {
+            self.bytes_received.fetch_add(len as u64, Ordering::Relaxed);
+            self.touch();
+        }
+    }
+
+    /// 进入发送队列,guard 释放时出队
+    #[inline]
+    pub(crate) fn enqueue(&self) -> QueueGuard<'_> {
+        self.queue_depth.fetch_add(1, Ordering::Relaxed);
+        QueueGuard(self)
+    }
+
+    /// 快照
+    #[inline]
+    pub(crate) fn snapshot(&self) -> PeerStats {
+        let last_active = Duration::from_millis(self.last_active.load(Ordering::Relaxed));
+        PeerStats {
+            bytes_sent: self.bytes_sent.load(Ordering::Relaxed),
+            bytes_received: self.bytes_received.load(Ordering::Relaxed),
+            messages_sent: self.messages_sent.load(Ordering::Relaxed),
+            queue_depth: self.queue_depth.load(Ordering::Relaxed),
+            connected_at: self.connected_at,
+            last_active: self.connected_at + last_active,
+            idle: self.start.elapsed().saturating_sub(last_active),
+        }
+    }
+}
+
+pub(crate) struct QueueGuard<'a>(&'a PeerCounters);
+
+impl Drop for QueueGuard<'_> {
+    #[inline]
+    fn drop(&mut self) {
+        self.0.queue_depth.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// 连接流量统计
+#[derive(Debug, Copy, Clone)]
+pub struct PeerStats {
+    /// 发送字节数
+    pub bytes_sent: u64,
+    /// 接收字节数
+    pub bytes_received: u64,
+    /// 完整发送的消息数,每次 send_all/send_file 计一次,send 只写入部分数据时不计入
+    pub messages_sent: u64,
+    /// 正在排队或执行中的 IPeer 调用数
+    pub queue_depth: usize,
+    /// 连接时间
+    pub connected_at: SystemTime,
+    /// 最后收发数据时间
+    pub last_active: SystemTime,
+    /// 距最后收发数据的时长
+    pub idle: Duration,
+}
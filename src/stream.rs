@@ -0,0 +1,45 @@
+use crate::peer::TCPPeer;
+use std::any::Any;
+use std::sync::Arc;
+use tokio::io::{AsyncRead, AsyncWrite};
+
+/// 可以装箱的流,stream_init 返回 BoxStream 时不同类型的流可以共用一个服务器类型
+pub trait AsyncStream: AsyncRead + AsyncWrite + Send + Unpin + 'static {
+    /// 用于识别装箱前的流类型,例如 TcpStream 使用 sendfile
+    fn as_any(&self) -> &dyn Any;
+
+    /// 装箱
+    #[inline]
+    fn boxed(self) -> BoxStream
+    where
+        Self: Sized,
+    {
+        Box::new(self)
+    }
+}
+
+impl<S> AsyncStream for S
+where
+    S: AsyncRead + AsyncWrite + Send + Unpin + 'static,
+{
+    #[inline]
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+/// 类型擦除的流
+pub type BoxStream = Box<dyn AsyncStream>;
+
+/// 使用 BoxStream 的 peer,TLS 和明文连接是同一个类型
+pub type BoxPeer = Arc<TCPPeer<BoxStream>>;
+
+/// 取得流的 Any,BoxStream 返回装箱前的流
+#[inline]
+pub(crate) fn stream_any<T: 'static>(stream: &T) -> &dyn Any {
+    let any = stream as &dyn Any;
+    match any.downcast_ref::<BoxStream>() {
+        Some(stream) => (**stream).as_any(),
+        None => any,
+    }
+}
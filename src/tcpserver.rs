@@ -1,53 +1,188 @@
+use crate::acl::{AccessList, SharedAccessList};
+use crate::connlimit::{Admission, ConnectLimiter, ConnectRateLimit};
 use crate::error::Result;
+use crate::instrument::{self, ConnectionSpan};
+use crate::lifecycle::{Connections, ServerState};
+use crate::listener::{Bind, Listener, PeerAddr, Rebind};
 use crate::peer::TCPPeer;
+use crate::ratelimit::{ServerLimits, Throttle};
+use crate::reader::PeerReader;
 use crate::IPeer;
 use aqueue::Actor;
 use log::*;
 use std::error::Error;
 use std::future::Future;
 use std::marker::PhantomData;
-use std::net::SocketAddr;
+use std::net::IpAddr;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
-use tokio::io::{AsyncRead, AsyncWrite, ReadHalf};
-use tokio::net::{TcpListener, TcpStream, ToSocketAddrs};
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::net::TcpListener;
+use tokio::sync::watch;
 use tokio::task::JoinHandle;
 
-pub type ConnectEventType = fn(SocketAddr) -> bool;
+/// 连接编号,用于 tracing span
+static NEXT_CONNECTION_ID: AtomicU64 = AtomicU64::new(1);
 
-pub struct TCPServer<I, R, T, B, C, IST> {
-    listener: Option<TcpListener>,
-    connect_event: Option<ConnectEventType>,
+pub type ConnectEventType = fn(&PeerAddr) -> bool;
+
+pub type DisconnectEventType<C, T> = Arc<
+    dyn Fn(Arc<TCPPeer<C>>, DisconnectReason, T) -> Pin<Box<dyn Future<Output = ()> + Send>>
+        + Send
+        + Sync,
+>;
+
+pub type PanicHandlerType = Arc<dyn Fn(&PeerAddr, &str) + Send + Sync>;
+
+/// 断线原因
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum DisconnectReason {
+    /// 客户端关闭连接,input_event 正常返回
+    ClientClosed,
+    /// input_event 返回错误
+    HandlerError(anyhow::Error),
+    /// input_event panic,附带 panic 信息
+    HandlerPanic(String),
+    /// 服务器调用 shutdown,等待超时后被中止
+    ServerShutdown,
+    /// 空闲超时,input_event 返回 Error::IdleTimeout
+    IdleTimeout,
+    /// 被服务器断开,调用 kick 时附带原因,调用 disconnect 时为 None
+    Kicked(Option<String>),
+}
+
+/// 暂停 accept 的方式
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum PauseMode {
+    /// 保持监听,新连接在 backlog 中排队,恢复后继续处理
+    #[default]
+    KeepListening,
+    /// 关闭监听 socket,新连接被拒绝,恢复时重新监听
+    CloseListener,
+}
+
+/// accept 状态
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum AcceptState {
+    /// 正常接受新连接
+    Accepting,
+    /// 已暂停,已有连接不受影响
+    Paused(PauseMode),
+}
+
+/// 连接事件,可以是 ConnectEventType 或者 ConnectionHandler::on_connect
+pub(crate) type ConnectFilter = Arc<dyn Fn(&PeerAddr) -> bool + Send + Sync>;
+
+/// 服务器可选配置,由 Builder 填充
+pub(crate) struct ServerOptions<C, T> {
+    pub(crate) connect_event: Option<ConnectFilter>,
+    pub(crate) disconnect_event: Option<DisconnectEventType<C, T>>,
+    pub(crate) panic_handler: Option<PanicHandlerType>,
+    pub(crate) accept_backoff: (Duration, Duration),
+    pub(crate) limits: ServerLimits,
+    pub(crate) connect_rate_limit: Option<ConnectRateLimit>,
+    pub(crate) access_list: Option<AccessList>,
+}
+
+impl<C, T> Default for ServerOptions<C, T> {
+    fn default() -> Self {
+        ServerOptions {
+            connect_event: None,
+            disconnect_event: None,
+            panic_handler: None,
+            accept_backoff: (Duration::from_millis(5), Duration::from_secs(1)),
+            limits: Default::default(),
+            connect_rate_limit: None,
+            access_list: None,
+        }
+    }
+}
+
+/// accept 错误分类
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum AcceptErrorKind {
+    /// 单个连接的错误,直接重试
+    Connection,
+    /// 资源耗尽等暂时性错误,退避后重试
+    Resource,
+    /// 监听 socket 已不可用
+    Fatal,
+}
+
+impl AcceptErrorKind {
+    fn classify(err: &std::io::Error) -> Self {
+        use std::io::ErrorKind::*;
+        match err.kind() {
+            ConnectionAborted | ConnectionReset | ConnectionRefused | Interrupted | WouldBlock
+            | TimedOut | PermissionDenied => return AcceptErrorKind::Connection,
+            InvalidInput | Unsupported => return AcceptErrorKind::Fatal,
+            _ => {}
+        }
+        #[cfg(unix)]
+        if let Some(code) = err.raw_os_error() {
+            if [libc::EBADF, libc::ENOTSOCK, libc::EOPNOTSUPP, libc::EFAULT].contains(&code) {
+                return AcceptErrorKind::Fatal;
+            }
+        }
+        AcceptErrorKind::Resource
+    }
+}
+
+pub struct TCPServer<I, R, T, B, C, IST, L = TcpListener> {
+    listener: Option<L>,
+    rebind: Rebind<L>,
+    options: ServerOptions<C, T>,
+    panic_count: Arc<AtomicU64>,
+    accept_error_count: Arc<AtomicU64>,
+    connect_limiter: Arc<ConnectLimiter>,
+    access_list: Arc<SharedAccessList>,
+    accept_state: Arc<watch::Sender<AcceptState>>,
+    state: Arc<watch::Sender<ServerState>>,
+    shutdown: Arc<watch::Sender<Option<Duration>>>,
+    connections: Arc<Connections>,
     stream_init: Arc<IST>,
     input_event: Arc<I>,
-    _phantom1: PhantomData<R>,
-    _phantom2: PhantomData<T>,
-    _phantom3: PhantomData<C>,
-    _phantom4: PhantomData<B>,
+    _phantom1: PhantomData<fn() -> R>,
+    _phantom2: PhantomData<fn(T)>,
+    _phantom3: PhantomData<fn() -> C>,
+    _phantom4: PhantomData<fn() -> B>,
 }
 
-unsafe impl<I, R, T, B, C, IST> Send for TCPServer<I, R, T, B, C, IST> {}
-unsafe impl<I, R, T, B, C, IST> Sync for TCPServer<I, R, T, B, C, IST> {}
-
-impl<I, R, T, B, C, IST> TCPServer<I, R, T, B, C, IST>
+impl<I, R, T, B, C, IST, L> TCPServer<I, R, T, B, C, IST, L>
 where
-    I: Fn(ReadHalf<C>, Arc<Actor<TCPPeer<C>>>, T) -> R + Send + Sync + 'static,
+    I: Fn(PeerReader<C>, Arc<TCPPeer<C>>, T) -> R + Send + Sync + 'static,
     R: Future<Output = anyhow::Result<()>> + Send + 'static,
     T: Clone + Send + 'static,
     B: Future<Output = anyhow::Result<C>> + Send + 'static,
     C: AsyncRead + AsyncWrite + Send + 'static,
-    IST: Fn(TcpStream) -> B + Send + Sync + 'static,
+    IST: Fn(L::Stream) -> B + Send + Sync + 'static,
+    L: Listener,
 {
     /// 创建一个新的TCP服务
-    pub(crate) async fn new<A: ToSocketAddrs>(
+    pub(crate) async fn new<A: Bind<Listener = L>>(
         addr: A,
         stream_init: IST,
         input: I,
-        connect_event: Option<ConnectEventType>,
-    ) -> Result<Arc<Actor<TCPServer<I, R, T, B, C, IST>>>, Box<dyn Error>> {
-        let listener = TcpListener::bind(addr).await?;
+        mut options: ServerOptions<C, T>,
+    ) -> Result<Arc<Actor<TCPServer<I, R, T, B, C, IST, L>>>, Box<dyn Error>> {
+        let listener = addr.bind().await?;
+        let connect_limiter = Arc::new(ConnectLimiter::new(options.connect_rate_limit));
+        let access_list = Arc::new(SharedAccessList::new(options.access_list.take()));
         Ok(Arc::new(Actor::new(TCPServer {
+            rebind: listener.rebind(),
             listener: Some(listener),
-            connect_event,
+            options,
+            panic_count: Default::default(),
+            accept_error_count: Default::default(),
+            connect_limiter,
+            access_list,
+            accept_state: Arc::new(watch::Sender::new(AcceptState::Accepting)),
+            state: Arc::new(watch::Sender::new(ServerState::Stopped(None))),
+            shutdown: Arc::new(watch::Sender::new(None)),
+            connections: Default::default(),
             stream_init: Arc::new(stream_init),
             input_event: Arc::new(input),
             _phantom1: Default::default(),
@@ -57,46 +192,387 @@ where
         })))
     }
 
-    /// 启动TCP服务
+    /// 连接任务 panic 次数
+    #[inline]
+    pub fn panic_count(&self) -> u64 {
+        self.panic_count.load(Ordering::Relaxed)
+    }
+
+    /// accept 错误次数
+    #[inline]
+    pub fn accept_error_count(&self) -> u64 {
+        self.accept_error_count.load(Ordering::Relaxed)
+    }
+
+    /// 封禁地址所在的地址段,duration 为 None 表示永久封禁
+    #[inline]
+    pub fn ban(&self, ip: IpAddr, duration: Option<Duration>) {
+        self.connect_limiter.ban(ip, duration)
+    }
+
+    /// 解除封禁,返回之前是否处于封禁状态
+    #[inline]
+    pub fn unban(&self, ip: IpAddr) -> bool {
+        self.connect_limiter.unban(ip)
+    }
+
+    /// 当前封禁的地址段和剩余封禁时间
+    #[inline]
+    pub fn banned(&self) -> Vec<(IpAddr, Option<Duration>)> {
+        self.connect_limiter.banned()
+    }
+
+    /// 替换访问控制列表,None 表示不限制,对之后的新连接生效
+    #[inline]
+    pub fn set_access_list(&self, acl: Option<AccessList>) {
+        self.access_list.store(acl)
+    }
+
+    /// 当前访问控制列表
+    #[inline]
+    pub fn access_list(&self) -> Option<Arc<AccessList>> {
+        self.access_list.load()
+    }
+
+    /// 暂停接受新连接,已有连接不受影响,未启动时对启动后生效
+    #[inline]
+    pub fn pause_accept(&self, mode: PauseMode) {
+        self.accept_state.send_replace(AcceptState::Paused(mode));
+    }
+
+    /// 恢复接受新连接
+    #[inline]
+    pub fn resume_accept(&self) {
+        self.accept_state.send_replace(AcceptState::Accepting);
+    }
+
+    /// 当前 accept 状态
+    #[inline]
+    pub fn accept_state(&self) -> AcceptState {
+        *self.accept_state.borrow()
+    }
+
+    /// 生命周期状态
+    #[inline]
+    pub fn state(&self) -> ServerState {
+        self.state.borrow().clone()
+    }
+
+    /// 订阅生命周期状态变化
+    #[inline]
+    pub fn watch_state(&self) -> watch::Receiver<ServerState> {
+        self.state.subscribe()
+    }
+
+    /// 当前连接数
+    #[inline]
+    pub fn connection_count(&self) -> usize {
+        self.connections.len()
+    }
+
+    /// 停止接受新连接并关闭监听,等待已有连接最多 grace 时间,
+    /// 之后中止剩余的连接,断线原因为 ServerShutdown
+    pub fn shutdown(&self, grace: Duration) {
+        let draining = self.state.send_if_modified(|state| {
+            if matches!(state, ServerState::Starting | ServerState::Running) {
+                *state = ServerState::Draining;
+                true
+            } else {
+                false
+            }
+        });
+        if draining {
+            self.shutdown.send_replace(Some(grace));
+        }
+    }
+
+    /// 启动TCP服务,停止后可以再次启动,重新监听原来的地址
     pub async fn start(&mut self, token: T) -> Result<JoinHandle<anyhow::Result<()>>> {
+        if self.listener.is_none() && self.state.borrow().is_stopped() {
+            self.listener = Some((self.rebind)().await?);
+        }
         if let Some(listener) = self.listener.take() {
-            let connect_event = self.connect_event.take();
+            let local_addr = listener.local_addr().ok();
+            let connect_event = self.options.connect_event.clone();
+            let disconnect_event = self.options.disconnect_event.clone();
+            let panic_handler = self.options.panic_handler.clone();
+            let panic_count = self.panic_count.clone();
+            let accept_error_count = self.accept_error_count.clone();
+            let connect_limiter = self.connect_limiter.clone();
+            let access_list = self.access_list.clone();
+            let (min_backoff, max_backoff) = self.options.accept_backoff;
+            let limits = self.options.limits.clone();
             let input_event = self.input_event.clone();
             let stream_init = self.stream_init.clone();
-            let join: JoinHandle<anyhow::Result<()>> = tokio::spawn(async move {
-                loop {
-                    let (socket, addr) = listener.accept().await?;
-                    if let Some(ref connect_event) = connect_event {
-                        if !connect_event(addr) {
-                            warn!("addr:{} not connect", addr);
-                            continue;
-                        }
+            // 任务持有 sender,服务器释放后 changed 不会立即返回错误
+            let accept_control = self.accept_state.clone();
+            let shutdown_control = self.shutdown.clone();
+            let server_state = self.state.clone();
+            let connections = self.connections.clone();
+            shutdown_control.send_replace(None);
+            server_state.send_replace(ServerState::Starting);
+            let drain = connections.clone();
+            let rebind = self.rebind.clone();
+            let server_addr = local_addr.clone();
+            let accept_task: JoinHandle<anyhow::Result<()>> = tokio::spawn(async move {
+                // 启动前可能已经调用 shutdown
+                server_state.send_if_modified(|state| {
+                    let starting = matches!(state, ServerState::Starting);
+                    if starting {
+                        *state = ServerState::Running;
                     }
-                    trace!("start read:{}", addr);
-                    let input = input_event.clone();
-                    let peer_token = token.clone();
-                    let stream_init = stream_init.clone();
-                    tokio::spawn(async move {
-                        match (*stream_init)(socket).await {
-                            Ok(socket) => {
-                                let (reader, sender) = tokio::io::split(socket);
-                                let peer = TCPPeer::new(addr, sender);
-                                if let Err(err) = (*input)(reader, peer.clone(), peer_token).await {
-                                    error!("input data error:{}", err);
+                    starting
+                });
+                // accept 循环,调用 shutdown 后返回等待时间,监听器随之关闭
+                let accept_loop = async move {
+                    let mut backoff = min_backoff;
+                    let mut accept_state = accept_control.subscribe();
+                    let mut shutdown = shutdown_control.subscribe();
+                    let mut listener = Some(listener);
+                    loop {
+                        if let Some(grace) = *shutdown.borrow_and_update() {
+                            return Ok(grace);
+                        }
+                        let state = *accept_state.borrow_and_update();
+                        if state == AcceptState::Paused(PauseMode::CloseListener) {
+                            if listener.take().is_some() {
+                                info!("accept paused, listener closed");
+                            }
+                        } else if listener.is_none() {
+                            match rebind().await {
+                                Ok(reopened) => {
+                                    info!("listener reopened");
+                                    listener = Some(reopened);
                                 }
-                                if let Err(er) = peer.disconnect().await {
-                                    debug!("disconnect client:{:?} err:{}", peer.addr(), er);
-                                } else {
-                                    debug!("{} disconnect", peer.addr())
+                                Err(err) => {
+                                    error!("reopen listener err:{}", err);
+                                    return Err(err);
                                 }
                             }
+                        }
+                        let current = match listener {
+                            Some(ref listener) if state == AcceptState::Accepting => listener,
+                            _ => {
+                                tokio::select! {
+                                    _ = accept_state.changed() => {},
+                                    _ = shutdown.changed() => {},
+                                }
+                                continue;
+                            }
+                        };
+                        let accepted = tokio::select! {
+                            accepted = current.accept() => accepted,
+                            _ = accept_state.changed() => continue,
+                            _ = shutdown.changed() => continue,
+                        };
+                        let (socket, addr) = match accepted {
+                            Ok(accept) => {
+                                backoff = min_backoff;
+                                accept
+                            }
                             Err(err) => {
-                                warn!("init stream err:{}", err);
+                                accept_error_count.fetch_add(1, Ordering::Relaxed);
+                                instrument::accept_error();
+                                match AcceptErrorKind::classify(&err) {
+                                    AcceptErrorKind::Connection => {
+                                        debug!("accept connection err:{}", err);
+                                    }
+                                    AcceptErrorKind::Resource => {
+                                        warn!("accept err:{} retry after {:?}", err, backoff);
+                                        tokio::time::sleep(backoff).await;
+                                        backoff = (backoff * 2).min(max_backoff);
+                                    }
+                                    AcceptErrorKind::Fatal => {
+                                        error!("accept fatal err:{}", err);
+                                        return Err(err);
+                                    }
+                                }
+                                continue;
+                            }
+                        };
+                        instrument::connection_accepted();
+                        if let Some(ip) = addr.ip() {
+                            if !access_list.is_allowed(ip) {
+                                debug!("addr:{} denied by access list", addr);
+                                instrument::connection_rejected("access_list");
+                                continue;
                             }
                         }
-                    });
+                        match addr
+                            .ip()
+                            .map_or(Admission::Allowed, |ip| connect_limiter.check(ip))
+                        {
+                            Admission::Allowed => {}
+                            Admission::RateLimited => {
+                                debug!("addr:{} connect rate limited", addr);
+                                instrument::connection_rejected("rate_limit");
+                                continue;
+                            }
+                            Admission::Banned => {
+                                debug!("addr:{} is banned", addr);
+                                instrument::connection_rejected("banned");
+                                continue;
+                            }
+                        }
+                        if let Some(ref connect_event) = connect_event {
+                            if !connect_event(&addr) {
+                                warn!("addr:{} not connect", addr);
+                                instrument::connection_rejected("connect_event");
+                                continue;
+                            }
+                        }
+                        trace!("start read:{}", addr);
+                        let input = input_event.clone();
+                        let peer_token = token.clone();
+                        let stream_init = stream_init.clone();
+                        let disconnect_event = disconnect_event.clone();
+                        let panic_handler = panic_handler.clone();
+                        let panic_count = panic_count.clone();
+                        let throttle = Throttle::new(&limits);
+                        let span = ConnectionSpan::new(
+                            &addr,
+                            NEXT_CONNECTION_ID.fetch_add(1, Ordering::Relaxed),
+                            local_addr.as_ref(),
+                        );
+                        let guard = connections.open();
+                        let connection_id = guard.id();
+                        let connection = tokio::spawn(span.clone().connection(async move {
+                            let handshake = Instant::now();
+                            let stream_init =
+                                tokio::spawn(span.stream_init((*stream_init)(socket)));
+                            guard.set_task(stream_init.abort_handle());
+                            let socket = match stream_init.await {
+                                Ok(Ok(socket)) => {
+                                    instrument::handshake_done(handshake.elapsed());
+                                    socket
+                                }
+                                Ok(Err(err)) => {
+                                    warn!("init stream err:{}", err);
+                                    instrument::handshake_failed();
+                                    return;
+                                }
+                                Err(err) if err.is_cancelled() => {
+                                    instrument::handshake_failed();
+                                    return;
+                                }
+                                Err(err) => {
+                                    instrument::handshake_failed();
+                                    let msg = panic_message(err);
+                                    report_panic(&panic_count, &panic_handler, &addr, &msg);
+                                    return;
+                                }
+                            };
+                            let (reader, peer) =
+                                TCPPeer::from_stream(addr.clone(), socket, throttle);
+                            let connected = Instant::now();
+                            instrument::connection_opened();
+                            let input_task = tokio::spawn(span.input_event((*input)(
+                                reader,
+                                peer.clone(),
+                                peer_token.clone(),
+                            )));
+                            guard.set_task(input_task.abort_handle());
+                            let result = input_task.await;
+                            // 先处理 panic 和错误,调用过 disconnect 或者 kick 不影响上报
+                            let closed_by_server = peer.is_closed_by_server();
+                            let reason = match result {
+                                Err(err) if err.is_cancelled() => DisconnectReason::ServerShutdown,
+                                Err(err) => {
+                                    let msg = panic_message(err);
+                                    report_panic(&panic_count, &panic_handler, &addr, &msg);
+                                    DisconnectReason::HandlerPanic(msg)
+                                }
+                                Ok(Ok(())) if closed_by_server => {
+                                    DisconnectReason::Kicked(peer.take_kick_reason())
+                                }
+                                Ok(Ok(())) => DisconnectReason::ClientClosed,
+                                // kick 之后读写返回的错误
+                                Ok(Err(err)) if closed_by_server && is_kick_error(&err) => {
+                                    debug!("addr:{} kicked:{}", addr, err);
+                                    DisconnectReason::Kicked(peer.take_kick_reason())
+                                }
+                                Ok(Err(err))
+                                    if matches!(
+                                        err.downcast_ref::<crate::error::Error>(),
+                                        Some(crate::error::Error::IdleTimeout)
+                                    ) =>
+                                {
+                                    debug!("addr:{} idle timeout", addr);
+                                    DisconnectReason::IdleTimeout
+                                }
+                                Ok(Err(err)) => {
+                                    error!("input data error:{}", err);
+                                    DisconnectReason::HandlerError(err)
+                                }
+                            };
+                            if let Err(er) = peer.disconnect().await {
+                                debug!("disconnect client:{:?} err:{}", peer.addr(), er);
+                            } else {
+                                debug!("{} disconnect", peer.addr())
+                            }
+                            instrument::connection_closed(connected.elapsed());
+                            if let Some(ref disconnect_event) = disconnect_event {
+                                // 断线事件 panic 不影响服务器,同样计入 panic_count
+                                let disconnect_task =
+                                    tokio::spawn((*disconnect_event)(peer, reason, peer_token));
+                                guard.set_disconnect_task(disconnect_task.abort_handle());
+                                if let Err(err) = disconnect_task.await {
+                                    if err.is_panic() {
+                                        let msg = panic_message(err);
+                                        report_panic(&panic_count, &panic_handler, &addr, &msg);
+                                    }
+                                }
+                            }
+                            drop(guard);
+                        }));
+                        // 断线处理超时后由 drain 中止整个连接任务
+                        connections.set_connection(connection_id, connection.abort_handle());
+                    }
+                };
+                let result: std::io::Result<Duration> = accept_loop.await;
+                match result {
+                    Ok(grace) => {
+                        info!("server draining, {} connections", drain.len());
+                        drain.drain(grace).await;
+                        server_state.send_replace(ServerState::Stopped(None));
+                        Ok(())
+                    }
+                    Err(err) => {
+                        let err = Arc::new(err);
+                        server_state.send_replace(ServerState::Stopped(Some(err.clone())));
+                        Err(anyhow::Error::new(err))
+                    }
                 }
             });
+            // accept 任务 panic 或者返回的 JoinHandle 被中止时同样进入 Stopped,
+            // 否则 shutdown 会一直等待
+            let stopped = StopOnDrop {
+                state: self.state.clone(),
+                accept_task: accept_task.abort_handle(),
+            };
+            let panic_handler = self.options.panic_handler.clone();
+            let panic_count = self.panic_count.clone();
+            let join = tokio::spawn(async move {
+                let result = match accept_task.await {
+                    Ok(result) => result,
+                    Err(err) => {
+                        let msg = panic_message(err);
+                        if let Some(ref addr) = server_addr {
+                            report_panic(&panic_count, &panic_handler, addr, &msg);
+                        } else {
+                            error!("accept task panic:{}", msg);
+                            panic_count.fetch_add(1, Ordering::Relaxed);
+                            instrument::task_panic();
+                        }
+                        let err =
+                            Arc::new(std::io::Error::other(format!("accept task panic:{}", msg)));
+                        stopped.stop(err.clone());
+                        Err(anyhow::Error::new(err))
+                    }
+                };
+                drop(stopped);
+                result
+            });
 
             Ok(join)
         } else {
@@ -105,21 +581,106 @@ where
     }
 }
 
+/// accept 任务意外结束时把状态设为 Stopped
+struct StopOnDrop {
+    state: Arc<watch::Sender<ServerState>>,
+    accept_task: tokio::task::AbortHandle,
+}
+
+impl StopOnDrop {
+    /// 尚未停止时进入 Stopped 并附带错误
+    fn stop(&self, err: Arc<std::io::Error>) {
+        self.state.send_if_modified(|state| {
+            if state.is_stopped() {
+                false
+            } else {
+                *state = ServerState::Stopped(Some(err));
+                true
+            }
+        });
+    }
+}
+
+impl Drop for StopOnDrop {
+    fn drop(&mut self) {
+        self.accept_task.abort();
+        self.stop(Arc::new(std::io::Error::new(
+            std::io::ErrorKind::Interrupted,
+            "accept task aborted",
+        )));
+    }
+}
+
+/// 取出 panic 信息
+fn panic_message(err: tokio::task::JoinError) -> String {
+    match err.try_into_panic() {
+        Ok(payload) => {
+            if let Some(msg) = payload.downcast_ref::<&str>() {
+                msg.to_string()
+            } else if let Some(msg) = payload.downcast_ref::<String>() {
+                msg.clone()
+            } else {
+                "unknown panic".to_string()
+            }
+        }
+        Err(err) => err.to_string(),
+    }
+}
+
+/// 是否是 kick 之后读写返回的错误
+fn is_kick_error(err: &anyhow::Error) -> bool {
+    let err = match err.downcast_ref::<crate::error::Error>() {
+        Some(crate::error::Error::IOError(err)) => Some(err),
+        _ => err.downcast_ref::<std::io::Error>(),
+    };
+    err.is_some_and(|err| err.kind() == std::io::ErrorKind::ConnectionAborted)
+}
+
+/// 记录 panic 并回调 panic handler
+fn report_panic(
+    panic_count: &AtomicU64,
+    panic_handler: &Option<PanicHandlerType>,
+    addr: &PeerAddr,
+    msg: &str,
+) {
+    error!("peer:{} task panic:{}", addr, msg);
+    panic_count.fetch_add(1, Ordering::Relaxed);
+    instrument::task_panic();
+    if let Some(ref panic_handler) = panic_handler {
+        (*panic_handler)(addr, msg);
+    }
+}
+
 #[async_trait::async_trait]
-pub trait ITCPServer<T> {
+pub trait ITCPServer<T>: Send + Sync {
     async fn start(&self, token: T) -> anyhow::Result<JoinHandle<anyhow::Result<()>>>;
     async fn start_block(&self, token: T) -> anyhow::Result<()>;
+    async fn panic_count(&self) -> u64;
+    async fn accept_error_count(&self) -> u64;
+    async fn ban(&self, ip: IpAddr, duration: Option<Duration>);
+    async fn unban(&self, ip: IpAddr) -> bool;
+    async fn banned(&self) -> Vec<(IpAddr, Option<Duration>)>;
+    async fn set_access_list(&self, acl: Option<AccessList>);
+    async fn access_list(&self) -> Option<Arc<AccessList>>;
+    async fn pause_accept(&self, mode: PauseMode);
+    async fn resume_accept(&self);
+    async fn accept_state(&self) -> AcceptState;
+    async fn state(&self) -> ServerState;
+    async fn watch_state(&self) -> watch::Receiver<ServerState>;
+    async fn connection_count(&self) -> usize;
+    async fn shutdown(&self, grace: Duration);
 }
 
 #[async_trait::async_trait]
-impl<I, R, T, B, C, IST> ITCPServer<T> for Actor<TCPServer<I, R, T, B, C, IST>>
+impl<I, R, T, B, C, IST, L> ITCPServer<T> for Actor<TCPServer<I, R, T, B, C, IST, L>>
 where
-    I: Fn(ReadHalf<C>, Arc<Actor<TCPPeer<C>>>, T) -> R + Send + Sync + 'static,
+    I: Fn(PeerReader<C>, Arc<TCPPeer<C>>, T) -> R + Send + Sync + 'static,
     R: Future<Output = anyhow::Result<()>> + Send + 'static,
     T: Clone + Send + Sync + 'static,
     B: Future<Output = anyhow::Result<C>> + Send + 'static,
     C: AsyncRead + AsyncWrite + Send + 'static,
-    IST: Fn(TcpStream) -> B + Send + Sync + 'static,
+    IST: Fn(L::Stream) -> B + Send + Sync + 'static,
+    L: Listener,
 {
     async fn start(&self, token: T) -> anyhow::Result<JoinHandle<anyhow::Result<()>>> {
         self.inner_call(|inner| async move { Ok(inner.get_mut().start(token).await?) })
@@ -130,4 +691,80 @@ where
         Self::start(self, token).await?.await??;
         Ok(())
     }
+
+    async fn panic_count(&self) -> u64 {
+        self.inner_call(|inner| async move { inner.get().panic_count() })
+            .await
+    }
+
+    async fn accept_error_count(&self) -> u64 {
+        self.inner_call(|inner| async move { inner.get().accept_error_count() })
+            .await
+    }
+
+    async fn ban(&self, ip: IpAddr, duration: Option<Duration>) {
+        self.inner_call(|inner| async move { inner.get().ban(ip, duration) })
+            .await
+    }
+
+    async fn unban(&self, ip: IpAddr) -> bool {
+        self.inner_call(|inner| async move { inner.get().unban(ip) })
+            .await
+    }
+
+    async fn banned(&self) -> Vec<(IpAddr, Option<Duration>)> {
+        self.inner_call(|inner| async move { inner.get().banned() })
+            .await
+    }
+
+    async fn set_access_list(&self, acl: Option<AccessList>) {
+        self.inner_call(|inner| async move { inner.get().set_access_list(acl) })
+            .await
+    }
+
+    async fn access_list(&self) -> Option<Arc<AccessList>> {
+        self.inner_call(|inner| async move { inner.get().access_list() })
+            .await
+    }
+
+    async fn pause_accept(&self, mode: PauseMode) {
+        self.inner_call(|inner| async move { inner.get().pause_accept(mode) })
+            .await
+    }
+
+    async fn resume_accept(&self) {
+        self.inner_call(|inner| async move { inner.get().resume_accept() })
+            .await
+    }
+
+    async fn accept_state(&self) -> AcceptState {
+        self.inner_call(|inner| async move { inner.get().accept_state() })
+            .await
+    }
+
+    async fn state(&self) -> ServerState {
+        self.inner_call(|inner| async move { inner.get().state() })
+            .await
+    }
+
+    async fn watch_state(&self) -> watch::Receiver<ServerState> {
+        self.inner_call(|inner| async move { inner.get().watch_state() })
+            .await
+    }
+
+    async fn connection_count(&self) -> usize {
+        self.inner_call(|inner| async move { inner.get().connection_count() })
+            .await
+    }
+
+    /// 停止服务,等待进入 Stopped 状态后返回
+    async fn shutdown(&self, grace: Duration) {
+        let mut state = self
+            .inner_call(|inner| async move {
+                inner.get().shutdown(grace);
+                inner.get().watch_state()
+            })
+            .await;
+        let _ = state.wait_for(ServerState::is_stopped).await;
+    }
 }
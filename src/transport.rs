@@ -0,0 +1,42 @@
+use crate::error::Result;
+use crate::listener::PeerAddr;
+use crate::peer::{IPeer, TCPPeer};
+use std::future::Future;
+use std::ops::Deref;
+use tokio::io::{AsyncRead, AsyncWrite};
+
+/// TCP 和 UDP 连接共有的操作,用于编写与传输层无关的代码
+pub trait ITransportPeer: Send + Sync {
+    /// 对端地址
+    fn addr(&self) -> PeerAddr;
+    /// 发送一段数据,UDP 为一个数据报
+    fn send<B: Deref<Target = [u8]> + Send + Sync + 'static>(
+        &self,
+        buff: B,
+    ) -> impl Future<Output = Result<usize>> + Send;
+    /// 断开连接
+    fn disconnect(&self) -> impl Future<Output = Result<()>> + Send;
+}
+
+impl<T> ITransportPeer for TCPPeer<T>
+where
+    T: AsyncRead + AsyncWrite + Send + 'static,
+{
+    #[inline]
+    fn addr(&self) -> PeerAddr {
+        IPeer::addr(self)
+    }
+
+    #[inline]
+    async fn send<B: Deref<Target = [u8]> + Send + Sync + 'static>(
+        &self,
+        buff: B,
+    ) -> Result<usize> {
+        IPeer::send(self, buff).await
+    }
+
+    #[inline]
+    async fn disconnect(&self) -> Result<()> {
+        IPeer::disconnect(self).await
+    }
+}
@@ -0,0 +1,90 @@
+use crate::udpserver::{UdpPeer, UdpReader, UdpServer};
+use crate::ConnectEventType;
+
+use aqueue::Actor;
+use std::future::Future;
+use std::marker::PhantomData;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::net::ToSocketAddrs;
+
+/// UDP server builder
+pub struct UdpBuilder<I, R, A, T> {
+    input: Option<I>,
+    connect_event: Option<ConnectEventType>,
+    idle_timeout: Duration,
+    queue: usize,
+    max_sessions: usize,
+    addr: A,
+    _phantom1: PhantomData<R>,
+    _phantom2: PhantomData<T>,
+}
+
+impl<I, R, A, T> UdpBuilder<I, R, A, T>
+where
+    I: Fn(UdpReader, Arc<UdpPeer>, T) -> R + Send + Sync + 'static,
+    R: Future<Output = anyhow::Result<()>> + Send + 'static,
+    A: ToSocketAddrs,
+    T: Clone + Send + 'static,
+{
+    pub fn new(addr: A) -> UdpBuilder<I, R, A, T> {
+        UdpBuilder {
+            input: None,
+            connect_event: None,
+            idle_timeout: Duration::from_secs(60),
+            queue: 1024,
+            max_sessions: 10240,
+            addr,
+            _phantom1: Default::default(),
+            _phantom2: Default::default(),
+        }
+    }
+
+    /// 设置UDP server 输入事件,每个对端地址一个虚拟连接
+    pub fn set_input_event(mut self, f: I) -> Self {
+        self.input = Some(f);
+        self
+    }
+
+    /// 设置UDP server 新虚拟连接事件
+    pub fn set_connect_event(mut self, c: ConnectEventType) -> Self {
+        self.connect_event = Some(c);
+        self
+    }
+
+    /// 设置虚拟连接空闲超时,默认60秒,由服务器每隔超时的一半检查一次,
+    /// 超时后关闭虚拟连接,recv 返回 None,input 任务到下一次检查时仍未结束则被中止
+    pub fn set_idle_timeout(mut self, timeout: Duration) -> Self {
+        self.idle_timeout = timeout;
+        self
+    }
+
+    /// 设置每个虚拟连接待处理数据报队列长度,满了之后丢弃,默认1024
+    pub fn set_queue_size(mut self, size: usize) -> Self {
+        self.queue = size.max(1);
+        self
+    }
+
+    /// 设置最大虚拟连接数,达到后丢弃新地址的数据报,默认10240
+    pub fn set_max_sessions(mut self, max: usize) -> Self {
+        self.max_sessions = max;
+        self
+    }
+
+    /// 生成UDPSERVER,如果没有设置 input 将报错
+    pub async fn build(mut self) -> Arc<Actor<UdpServer<I, R, T>>> {
+        if let Some(input) = self.input.take() {
+            return UdpServer::new(
+                self.addr,
+                input,
+                self.connect_event,
+                self.idle_timeout,
+                self.queue,
+                self.max_sessions,
+            )
+            .await
+            .unwrap();
+        }
+        panic!("input event is no settings,please use set_input_event function set input event.");
+    }
+}
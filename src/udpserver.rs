@@ -0,0 +1,316 @@
+use crate::error::Result;
+use crate::listener::PeerAddr;
+use crate::transport::ITransportPeer;
+use crate::ConnectEventType;
+use aqueue::Actor;
+use log::*;
+use std::collections::HashMap;
+use std::future::Future;
+use std::io::ErrorKind;
+use std::marker::PhantomData;
+use std::net::SocketAddr;
+use std::ops::Deref;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, Weak};
+use std::time::{Duration, Instant};
+use tokio::net::{ToSocketAddrs, UdpSocket};
+use tokio::sync::mpsc::{self, error::TrySendError};
+use tokio::task::{AbortHandle, JoinHandle};
+
+/// UDP 数据报最大长度
+const MAX_DATAGRAM: usize = 65536;
+
+/// 虚拟连接登记项
+struct Session {
+    peer: Arc<UdpPeer>,
+    tx: mpsc::Sender<Vec<u8>>,
+    /// 最后收到数据报的时间
+    last_active: Instant,
+    /// input 任务,空闲超时关闭后仍不结束时中止
+    task: AbortHandle,
+}
+
+type Sessions = Mutex<HashMap<SocketAddr, Session>>;
+
+/// 关闭空闲超过 idle_timeout 的虚拟连接,之后 recv 返回 None,
+/// 上一次检查关闭的连接如果 input 任务还没有结束则中止
+fn expire_sessions(sessions: &Sessions, idle_timeout: Duration, closing: &mut Vec<AbortHandle>) {
+    for task in closing.drain(..) {
+        task.abort();
+    }
+    sessions.lock().unwrap().retain(|addr, session| {
+        if session.last_active.elapsed() < idle_timeout {
+            return true;
+        }
+        debug!("addr:{} udp session idle timeout", addr);
+        session.peer.closed.store(true, Ordering::Release);
+        closing.push(session.task.clone());
+        false
+    });
+}
+
+/// UDP 虚拟连接,按对端地址区分
+pub struct UdpPeer {
+    addr: SocketAddr,
+    socket: Arc<UdpSocket>,
+    sessions: Weak<Sessions>,
+    closed: AtomicBool,
+}
+
+impl UdpPeer {
+    /// 对端 socket 地址,ITransportPeer::addr 返回 PeerAddr
+    #[inline]
+    pub fn socket_addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    /// 是否断线
+    #[inline]
+    pub fn is_disconnect(&self) -> bool {
+        self.closed.load(Ordering::Acquire)
+    }
+
+    /// 发送一个数据报,不需要转移所有权
+    #[inline]
+    pub async fn send_ref(&self, buff: &[u8]) -> Result<usize> {
+        if self.is_disconnect() {
+            return Err(std::io::Error::from(ErrorKind::ConnectionReset).into());
+        }
+        Ok(self.socket.send_to(buff, self.addr).await?)
+    }
+
+    /// 断开虚拟连接,之后收到此地址的数据报会创建新的连接
+    #[inline]
+    pub(crate) fn close(&self) {
+        if !self.closed.swap(true, Ordering::AcqRel) {
+            if let Some(sessions) = self.sessions.upgrade() {
+                let mut sessions = sessions.lock().unwrap();
+                if let Some(session) = sessions.get(&self.addr) {
+                    if std::ptr::eq(Arc::as_ptr(&session.peer), self) {
+                        sessions.remove(&self.addr);
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl ITransportPeer for UdpPeer {
+    #[inline]
+    fn addr(&self) -> PeerAddr {
+        PeerAddr::Udp(self.addr)
+    }
+
+    #[inline]
+    async fn send<B: Deref<Target = [u8]> + Send + Sync + 'static>(
+        &self,
+        buff: B,
+    ) -> Result<usize> {
+        self.send_ref(&buff).await
+    }
+
+    #[inline]
+    async fn disconnect(&self) -> Result<()> {
+        self.close();
+        Ok(())
+    }
+}
+
+/// UDP 虚拟连接的数据报接收端
+pub struct UdpReader {
+    rx: mpsc::Receiver<Vec<u8>>,
+    peer: Arc<UdpPeer>,
+}
+
+impl UdpReader {
+    /// 接收下一个数据报,断开连接后返回 None
+    pub async fn recv(&mut self) -> Option<Vec<u8>> {
+        let data = self.rx.recv().await;
+        if data.is_none() {
+            self.peer.close();
+        }
+        data
+    }
+}
+
+/// UDP server
+pub struct UdpServer<I, R, T> {
+    socket: Option<UdpSocket>,
+    sessions: Arc<Sessions>,
+    connect_event: Option<ConnectEventType>,
+    idle_timeout: Duration,
+    queue: usize,
+    max_sessions: usize,
+    input_event: Arc<I>,
+    _phantom: PhantomData<fn() -> (R, T)>,
+}
+
+impl<I, R, T> UdpServer<I, R, T>
+where
+    I: Fn(UdpReader, Arc<UdpPeer>, T) -> R + Send + Sync + 'static,
+    R: Future<Output = anyhow::Result<()>> + Send + 'static,
+    T: Clone + Send + 'static,
+{
+    /// 创建一个新的UDP服务
+    pub(crate) async fn new<A: ToSocketAddrs>(
+        addr: A,
+        input: I,
+        connect_event: Option<ConnectEventType>,
+        idle_timeout: Duration,
+        queue: usize,
+        max_sessions: usize,
+    ) -> Result<Arc<Actor<UdpServer<I, R, T>>>> {
+        let socket = UdpSocket::bind(addr).await?;
+        Ok(Arc::new(Actor::new(UdpServer {
+            socket: Some(socket),
+            sessions: Default::default(),
+            connect_event,
+            idle_timeout,
+            queue,
+            max_sessions,
+            input_event: Arc::new(input),
+            _phantom: PhantomData,
+        })))
+    }
+
+    /// 当前虚拟连接数
+    #[inline]
+    pub fn session_count(&self) -> usize {
+        self.sessions.lock().unwrap().len()
+    }
+
+    /// 启动UDP服务
+    pub async fn start(&mut self, token: T) -> Result<JoinHandle<anyhow::Result<()>>> {
+        if let Some(socket) = self.socket.take() {
+            let socket = Arc::new(socket);
+            let sessions = self.sessions.clone();
+            let connect_event = self.connect_event;
+            let idle_timeout = self.idle_timeout;
+            let queue = self.queue;
+            let max_sessions = self.max_sessions;
+            let input_event = self.input_event.clone();
+            let join: JoinHandle<anyhow::Result<()>> = tokio::spawn(async move {
+                let mut buff = vec![0; MAX_DATAGRAM];
+                // 由服务器定时检查空闲,不依赖 input 调用 recv
+                let mut sweep =
+                    tokio::time::interval((idle_timeout / 2).max(Duration::from_millis(1)));
+                sweep.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+                let mut closing = Vec::new();
+                loop {
+                    let recv = tokio::select! {
+                        recv = socket.recv_from(&mut buff) => recv,
+                        _ = sweep.tick() => {
+                            expire_sessions(&sessions, idle_timeout, &mut closing);
+                            continue;
+                        }
+                    };
+                    let (len, addr) = match recv {
+                        Ok(recv) => recv,
+                        Err(err) => {
+                            // 之前发送的数据报被拒绝等错误不影响其他对端
+                            debug!("udp recv err:{}", err);
+                            continue;
+                        }
+                    };
+                    let data = buff[..len].to_vec();
+                    let mut sessions_guard = sessions.lock().unwrap();
+                    let data = match sessions_guard.get_mut(&addr) {
+                        Some(session) => match session.tx.try_send(data) {
+                            Ok(()) => {
+                                session.last_active = Instant::now();
+                                continue;
+                            }
+                            Err(TrySendError::Full(_)) => {
+                                session.last_active = Instant::now();
+                                debug!("addr:{} udp queue full,drop datagram", addr);
+                                continue;
+                            }
+                            Err(TrySendError::Closed(data)) => {
+                                sessions_guard.remove(&addr);
+                                data
+                            }
+                        },
+                        None => data,
+                    };
+
+                    if sessions_guard.len() >= max_sessions {
+                        debug!("addr:{} udp sessions full,drop datagram", addr);
+                        continue;
+                    }
+                    if let Some(connect_event) = connect_event {
+                        if !connect_event(&PeerAddr::Udp(addr)) {
+                            warn!("addr:{} not connect", addr);
+                            continue;
+                        }
+                    }
+                    trace!("addr:{} udp session start", addr);
+                    let (tx, rx) = mpsc::channel(queue);
+                    let _ = tx.try_send(data);
+                    let peer = Arc::new(UdpPeer {
+                        addr,
+                        socket: socket.clone(),
+                        sessions: Arc::downgrade(&sessions),
+                        closed: AtomicBool::new(false),
+                    });
+                    let reader = UdpReader {
+                        rx,
+                        peer: peer.clone(),
+                    };
+                    let input_event = input_event.clone();
+                    let token = token.clone();
+                    let session_peer = peer.clone();
+                    // 持有锁时启动,任务结束时的 disconnect 一定在登记之后
+                    let task = tokio::spawn(async move {
+                        if let Err(err) = (*input_event)(reader, peer.clone(), token).await {
+                            error!("input data error:{}", err);
+                        }
+                        peer.close();
+                        debug!("{} udp session end", addr);
+                    });
+                    sessions_guard.insert(
+                        addr,
+                        Session {
+                            peer: session_peer,
+                            tx,
+                            last_active: Instant::now(),
+                            task: task.abort_handle(),
+                        },
+                    );
+                }
+            });
+            Ok(join)
+        } else {
+            Err(crate::error::Error::NotListenerError)
+        }
+    }
+}
+
+#[async_trait::async_trait]
+pub trait IUdpServer<T> {
+    async fn start(&self, token: T) -> anyhow::Result<JoinHandle<anyhow::Result<()>>>;
+    async fn start_block(&self, token: T) -> anyhow::Result<()>;
+    async fn session_count(&self) -> usize;
+}
+
+#[async_trait::async_trait]
+impl<I, R, T> IUdpServer<T> for Actor<UdpServer<I, R, T>>
+where
+    I: Fn(UdpReader, Arc<UdpPeer>, T) -> R + Send + Sync + 'static,
+    R: Future<Output = anyhow::Result<()>> + Send + 'static,
+    T: Clone + Send + Sync + 'static,
+{
+    async fn start(&self, token: T) -> anyhow::Result<JoinHandle<anyhow::Result<()>>> {
+        self.inner_call(|inner| async move { Ok(inner.get_mut().start(token).await?) })
+            .await
+    }
+
+    async fn start_block(&self, token: T) -> anyhow::Result<()> {
+        Self::start(self, token).await?.await??;
+        Ok(())
+    }
+
+    async fn session_count(&self) -> usize {
+        self.inner_call(|inner| async move { inner.get().session_count() })
+            .await
+    }
+}
@@ -0,0 +1,126 @@
+use futures_util::{Sink, Stream};
+use std::io;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tokio_tungstenite::tungstenite::protocol::WebSocketConfig;
+use tokio_tungstenite::tungstenite::{Bytes, Error as WsError, Message};
+
+/// 在 stream_init 中完成 WebSocket 握手,例如
+/// `.set_stream_init(tcpserver::websocket::accept)`
+pub async fn accept<S>(stream: S) -> anyhow::Result<WebSocketStream<S>>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    accept_with_config(stream, WebSocketConfig::default()).await
+}
+
+/// 使用指定配置完成 WebSocket 握手
+pub async fn accept_with_config<S>(
+    stream: S,
+    config: WebSocketConfig,
+) -> anyhow::Result<WebSocketStream<S>>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    // 每次 send 对应一个帧,不在 tungstenite 内部攒批
+    let config = config.write_buffer_size(0);
+    let inner = tokio_tungstenite::accept_async_with_config(stream, Some(config)).await?;
+    Ok(WebSocketStream {
+        inner,
+        read_buf: Bytes::new(),
+    })
+}
+
+/// WebSocket 连接的字节流适配
+///
+/// 读取时返回二进制帧和文本帧的内容,ping 自动回复 pong,收到 close 帧视为 EOF;
+/// 每次写入发送一个二进制帧,shutdown 发送 close 帧
+pub struct WebSocketStream<S> {
+    inner: tokio_tungstenite::WebSocketStream<S>,
+    read_buf: Bytes,
+}
+
+impl<S> WebSocketStream<S> {
+    /// 底层 tungstenite 流,可以直接收发 Message
+    #[inline]
+    pub fn into_inner(self) -> tokio_tungstenite::WebSocketStream<S> {
+        self.inner
+    }
+}
+
+#[inline]
+fn into_io_error(err: WsError) -> io::Error {
+    match err {
+        WsError::Io(err) => err,
+        err => io::Error::other(err),
+    }
+}
+
+impl<S> AsyncRead for WebSocketStream<S>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        while self.read_buf.is_empty() {
+            match Pin::new(&mut self.inner).poll_next(cx) {
+                Poll::Ready(Some(Ok(Message::Binary(data)))) => self.read_buf = data,
+                Poll::Ready(Some(Ok(Message::Text(text)))) => self.read_buf = text.into(),
+                // ping 的 pong 回复由 tungstenite 排队发送
+                Poll::Ready(Some(Ok(Message::Ping(_) | Message::Pong(_) | Message::Frame(_)))) => {}
+                Poll::Ready(Some(Ok(Message::Close(_))))
+                | Poll::Ready(None)
+                | Poll::Ready(Some(Err(WsError::ConnectionClosed | WsError::AlreadyClosed))) => {
+                    return Poll::Ready(Ok(()))
+                }
+                Poll::Ready(Some(Err(err))) => return Poll::Ready(Err(into_io_error(err))),
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+        let len = self.read_buf.len().min(buf.remaining());
+        let data = self.read_buf.split_to(len);
+        buf.put_slice(&data);
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl<S> AsyncWrite for WebSocketStream<S>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let mut inner = Pin::new(&mut self.inner);
+        if let Err(err) = futures_util::ready!(inner.as_mut().poll_ready(cx)) {
+            return Poll::Ready(Err(into_io_error(err)));
+        }
+        if let Err(err) = inner.as_mut().start_send(Message::binary(buf.to_vec())) {
+            return Poll::Ready(Err(into_io_error(err)));
+        }
+        // 帧已经进入发送缓冲,没写完的部分在下次写入或 flush 时发送
+        if let Poll::Ready(Err(err)) = inner.poll_flush(cx) {
+            return Poll::Ready(Err(into_io_error(err)));
+        }
+        Poll::Ready(Ok(buf.len()))
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner)
+            .poll_flush(cx)
+            .map_err(into_io_error)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        match futures_util::ready!(Pin::new(&mut self.inner).poll_close(cx)) {
+            Ok(()) | Err(WsError::ConnectionClosed | WsError::AlreadyClosed) => Poll::Ready(Ok(())),
+            Err(err) => Poll::Ready(Err(into_io_error(err))),
+        }
+    }
+}
@@ -0,0 +1,48 @@
+use anyhow::Result;
+use tcpserver::{Bind, Builder, ITCPServer, Listener, PeerAddr, ServerState};
+
+/// 每次 accept 都失败的监听器
+struct BrokenListener;
+
+impl Listener for BrokenListener {
+    type Stream = tokio::net::TcpStream;
+
+    async fn accept(&self) -> std::io::Result<(tokio::net::TcpStream, PeerAddr)> {
+        Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            "listener broken",
+        ))
+    }
+
+    fn local_addr(&self) -> std::io::Result<PeerAddr> {
+        Err(std::io::ErrorKind::Unsupported.into())
+    }
+}
+
+struct BrokenBind;
+
+impl Bind for BrokenBind {
+    type Listener = BrokenListener;
+
+    async fn bind(self) -> std::io::Result<BrokenListener> {
+        Ok(BrokenListener)
+    }
+}
+
+/// accept 致命错误后进入 Stopped 状态并带上错误
+#[tokio::test]
+async fn accept_fatal_stops_server() -> Result<()> {
+    let tcpserver = Builder::new(BrokenBind)
+        .set_stream_init(|stream| async move { Ok(stream) })
+        .set_input_event(|_, _, _: ()| async move { Ok(()) })
+        .build()
+        .await;
+    let mut state = tcpserver.watch_state().await;
+    let join = tcpserver.start(()).await?;
+    let stopped = state.wait_for(ServerState::is_stopped).await?.clone();
+    assert_eq!(stopped.error().unwrap().to_string(), "listener broken");
+    assert_eq!(join.await?.unwrap_err().to_string(), "listener broken");
+    // 不支持 rebind 的监听器不能重新启动
+    assert!(tcpserver.start(()).await.is_err());
+    Ok(())
+}
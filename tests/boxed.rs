@@ -0,0 +1,64 @@
+#![cfg(unix)]
+
+use anyhow::Result;
+use std::sync::{Arc, Mutex};
+use tcpserver::{
+    AsyncStream, BoxPeer, BoxStream, Builder, IPeer, ITCPServer, PeerReader, UnixBind,
+};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+type Registry = Arc<Mutex<Vec<BoxPeer>>>;
+
+/// TCP 和 Unix 连接共用的处理函数
+async fn handler(
+    mut reader: PeerReader<BoxStream>,
+    peer: BoxPeer,
+    registry: Registry,
+) -> anyhow::Result<()> {
+    registry.lock().unwrap().push(peer);
+    let mut buff = [0; 64];
+    loop {
+        let len = reader.read(&mut buff).await?;
+        if len == 0 {
+            break;
+        }
+        let peers = registry.lock().unwrap().clone();
+        for peer in peers {
+            peer.send(buff[..len].to_vec()).await?;
+        }
+    }
+    Ok(())
+}
+
+#[tokio::test]
+async fn boxed_stream_broadcast() -> Result<()> {
+    let registry = Registry::default();
+    let tcpserver = Builder::new("127.0.0.1:5573")
+        .set_stream_init(|tcp_stream| async move { Ok(tcp_stream.boxed()) })
+        .set_input_event(handler)
+        .build()
+        .await;
+    tcpserver.start(registry.clone()).await?;
+
+    let path = std::env::temp_dir().join(format!("tcpserver_boxed_{}.sock", std::process::id()));
+    let unixserver = Builder::new(UnixBind::new(&path))
+        .set_stream_init(|stream| async move { Ok(stream.boxed()) })
+        .set_input_event(handler)
+        .build()
+        .await;
+    unixserver.start(registry.clone()).await?;
+
+    let mut tcp_stream = tokio::net::TcpStream::connect("127.0.0.1:5573").await?;
+    let mut unix_stream = tokio::net::UnixStream::connect(&path).await?;
+    while registry.lock().unwrap().len() < 2 {
+        tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+    }
+
+    tcp_stream.write_all(b"hi").await?;
+    let mut buff = [0; 2];
+    tcp_stream.read_exact(&mut buff).await?;
+    assert_eq!(&buff, b"hi");
+    unix_stream.read_exact(&mut buff).await?;
+    assert_eq!(&buff, b"hi");
+    Ok(())
+}
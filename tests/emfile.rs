@@ -0,0 +1,54 @@
+// setrlimit 影响整个进程,单独一个测试程序,避免和其他测试并行
+#![cfg(unix)]
+
+use anyhow::Result;
+use std::time::Duration;
+use tcpserver::{Builder, ITCPServer};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+/// 文件句柄耗尽时 accept 循环不能退出
+#[tokio::test]
+async fn accept_survives_emfile() -> Result<()> {
+    let tcpserver = Builder::new("127.0.0.1:5561")
+        .set_stream_init(|tcp_stream| async move { Ok(tcp_stream) })
+        .set_input_event(|mut reader, _, _| async move {
+            let mut buff = [0; 16];
+            while reader.read(&mut buff).await? > 0 {}
+            Ok(())
+        })
+        .set_accept_backoff(Duration::from_millis(1), Duration::from_millis(20))
+        .build()
+        .await;
+    let join = tcpserver.start(()).await?;
+
+    let mut limit = libc::rlimit {
+        rlim_cur: 0,
+        rlim_max: 0,
+    };
+    unsafe { libc::getrlimit(libc::RLIMIT_NOFILE, &mut limit) };
+    let old_limit = limit.rlim_cur;
+    limit.rlim_cur = 64;
+    unsafe { libc::setrlimit(libc::RLIMIT_NOFILE, &limit) };
+
+    let mut clients = Vec::new();
+    while let Ok(Ok(stream)) = tokio::time::timeout(
+        Duration::from_millis(200),
+        tokio::net::TcpStream::connect("127.0.0.1:5561"),
+    )
+    .await
+    {
+        clients.push(stream);
+    }
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    assert!(tcpserver.accept_error_count().await > 0);
+
+    drop(clients);
+    limit.rlim_cur = old_limit;
+    unsafe { libc::setrlimit(libc::RLIMIT_NOFILE, &limit) };
+
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    assert!(!join.is_finished());
+    let mut tcp_stream = tokio::net::TcpStream::connect("127.0.0.1:5561").await?;
+    tcp_stream.write_all(b"hello").await?;
+    Ok(())
+}
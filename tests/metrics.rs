@@ -0,0 +1,63 @@
+#![cfg(feature = "metrics")]
+
+use anyhow::Result;
+use metrics_util::debugging::{DebugValue, DebuggingRecorder};
+use std::collections::HashMap;
+use std::time::Duration;
+use tcpserver::{Builder, IPeer, ITCPServer};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+#[tokio::test]
+async fn metrics_counters() -> Result<()> {
+    let recorder = DebuggingRecorder::new();
+    let snapshotter = recorder.snapshotter();
+    recorder.install().unwrap();
+
+    let tcpserver = Builder::new("127.0.0.1:5562")
+        .set_stream_init(|tcp_stream| async move { Ok(tcp_stream) })
+        .set_input_event(|mut reader, peer, _| async move {
+            let mut buff = [0; 4096];
+            loop {
+                let len = reader.read(&mut buff).await?;
+                if len == 0 {
+                    break;
+                }
+                peer.send_all(buff[..len].to_vec()).await?;
+            }
+            Ok(())
+        })
+        .build()
+        .await;
+    tcpserver.start(()).await?;
+
+    let mut tcp_stream = tokio::net::TcpStream::connect("127.0.0.1:5562").await?;
+    tcp_stream.write_all(b"hello").await?;
+    let mut buff = [0; 5];
+    tcp_stream.read_exact(&mut buff).await?;
+    drop(tcp_stream);
+    tokio::time::sleep(Duration::from_millis(50)).await;
+
+    let values = snapshotter
+        .snapshot()
+        .into_vec()
+        .into_iter()
+        .map(|(key, _, _, value)| (key.key().name().to_string(), value))
+        .collect::<HashMap<_, _>>();
+    let counter = |name: &str| match values.get(name) {
+        Some(DebugValue::Counter(value)) => *value,
+        _ => 0,
+    };
+    assert_eq!(counter("tcpserver_connections_accepted_total"), 1);
+    assert_eq!(counter("tcpserver_bytes_received_total"), 5);
+    assert_eq!(counter("tcpserver_bytes_sent_total"), 5);
+    assert_eq!(counter("tcpserver_messages_sent_total"), 1);
+    assert!(matches!(
+        values.get("tcpserver_connections_active"),
+        Some(DebugValue::Gauge(value)) if value.into_inner() == 0.0
+    ));
+    assert!(matches!(
+        values.get("tcpserver_handshake_duration_seconds"),
+        Some(DebugValue::Histogram(values)) if values.len() == 1
+    ));
+    Ok(())
+}
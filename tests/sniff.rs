@@ -0,0 +1,105 @@
+use anyhow::Result;
+use std::sync::Arc;
+use std::time::Duration;
+use tcpserver::{Builder, IPeer, ITCPServer, Protocol, Sniffer};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+#[test]
+fn detect_protocol() {
+    assert_eq!(Protocol::detect(&[0x16, 0x03, 0x01]), Some(Protocol::Tls));
+    assert_eq!(Protocol::detect(&[0x16]), None);
+    assert_eq!(Protocol::detect(b"GET / HTTP/1.1"), Some(Protocol::Http));
+    assert_eq!(Protocol::detect(b"PO"), None);
+    assert_eq!(
+        Protocol::detect(b"PROXY TCP4 1.1.1.1"),
+        Some(Protocol::ProxyV1)
+    );
+    assert_eq!(
+        Protocol::detect(b"\r\n\r\n\0\r\nQUIT\n\x21"),
+        Some(Protocol::ProxyV2)
+    );
+    assert_eq!(Protocol::detect(b"hello"), Some(Protocol::Plain));
+}
+
+/// 回复探测到的协议后回显一行
+async fn probe<S: AsyncRead + AsyncWrite + Unpin>(stream: &mut S, first: &[u8]) -> Result<String> {
+    stream.write_all(first).await?;
+    let mut buff = [0; 64];
+    let len = stream.read(&mut buff).await?;
+    Ok(String::from_utf8_lossy(&buff[..len]).into_owned())
+}
+
+#[tokio::test]
+async fn sniff_protocol() -> Result<()> {
+    let sniffer = Arc::new({
+        let sniffer = Sniffer::new().set_timeout(Duration::from_millis(200));
+        #[cfg(feature = "tls")]
+        let sniffer = sniffer.set_tls_acceptor(acceptor()?);
+        sniffer
+    });
+    let tcpserver = Builder::new("127.0.0.1:5572")
+        .set_stream_init(move |tcp_stream| {
+            let sniffer = sniffer.clone();
+            async move { sniffer.accept(tcp_stream).await }
+        })
+        .set_input_event(|mut reader, peer, _| async move {
+            let protocol = peer.ext::<Protocol>().await.unwrap();
+            let mut buff = [0; 64];
+            let len = reader.read(&mut buff).await?;
+            let reply = format!("{:?}:{}", protocol, String::from_utf8_lossy(&buff[..len]));
+            peer.send_all(reply.into_bytes()).await?;
+            Ok(())
+        })
+        .build()
+        .await;
+    tcpserver.start(()).await?;
+
+    for (first, expect) in [
+        (&b"hello"[..], "Plain:hello"),
+        (b"GET / HTTP/1.1\r\n", "Http:GET / HTTP/1.1\r\n"),
+        (b"PROXY TCP4 ", "ProxyV1:PROXY TCP4 "),
+    ] {
+        let mut tcp_stream = tokio::net::TcpStream::connect("127.0.0.1:5572").await?;
+        assert_eq!(probe(&mut tcp_stream, first).await?, expect);
+    }
+
+    // 首包分两次到达时等待后续数据
+    let mut tcp_stream = tokio::net::TcpStream::connect("127.0.0.1:5572").await?;
+    tcp_stream.write_all(b"PO").await?;
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    assert_eq!(probe(&mut tcp_stream, b"ST /").await?, "Http:POST /");
+
+    // 超时没有发送数据的连接被拒绝
+    let mut tcp_stream = tokio::net::TcpStream::connect("127.0.0.1:5572").await?;
+    let start = std::time::Instant::now();
+    let closed = tcp_stream
+        .read(&mut [0; 1])
+        .await
+        .map_or(true, |len| len == 0);
+    assert!(closed);
+    assert!(start.elapsed() >= Duration::from_millis(150));
+
+    #[cfg(feature = "tls")]
+    {
+        use openssl::ssl::{SslConnector, SslMethod, SslVerifyMode};
+        let mut connector = SslConnector::builder(SslMethod::tls())?;
+        connector.set_verify(SslVerifyMode::NONE);
+        let ssl = connector.build().configure()?.into_ssl("localhost")?;
+        let tcp_stream = tokio::net::TcpStream::connect("127.0.0.1:5572").await?;
+        let mut stream = tokio_openssl::SslStream::new(ssl, tcp_stream)?;
+        std::pin::Pin::new(&mut stream).connect().await?;
+        assert_eq!(probe(&mut stream, b"secret").await?, "Tls:secret");
+    }
+    Ok(())
+}
+
+#[cfg(feature = "tls")]
+fn acceptor() -> Result<openssl::ssl::SslAcceptor> {
+    use openssl::ssl::{SslAcceptor, SslFiletype, SslMethod};
+    let mut acceptor = SslAcceptor::mozilla_intermediate(SslMethod::tls())?;
+    // 仓库中的测试证书密钥较短
+    acceptor.set_security_level(0);
+    acceptor.set_private_key_file("tests/server-key.pem", SslFiletype::PEM)?;
+    acceptor.set_certificate_chain_file("tests/server-cert.pem")?;
+    Ok(acceptor.build())
+}
@@ -0,0 +1,104 @@
+use anyhow::Result;
+use std::convert::TryInto;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tcpserver::{Builder, HandlerServer, IPeer, ITCPServer, TCPPeer};
+use tokio::io::AsyncReadExt;
+use tokio::net::TcpStream;
+
+const TASKS: u32 = 8;
+const MESSAGES: u32 = 200;
+const CONNECTIONS: usize = 20;
+
+#[test]
+fn send_sync() {
+    fn assert_send_sync<T: Send + Sync>() {}
+    assert_send_sync::<TCPPeer<TcpStream>>();
+    assert_send_sync::<HandlerServer<TcpStream, ()>>();
+}
+
+/// 多个任务并发发送,同时另一个任务断开连接;
+/// 成功的发送必须完整到达,断开之后的发送必须失败
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn concurrent_send_disconnect() -> Result<()> {
+    let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+    let tcpserver = Builder::new("127.0.0.1:5577")
+        .set_stream_init(|tcp_stream| async move { Ok(tcp_stream) })
+        .set_input_event(move |_, peer, _| {
+            let tx = tx.clone();
+            async move {
+                let sent = Arc::new(AtomicUsize::new(0));
+                let mut tasks = Vec::new();
+                for id in 0..TASKS {
+                    let peer = peer.clone();
+                    let sent = sent.clone();
+                    tasks.push(tokio::spawn(async move {
+                        let mut failed = false;
+                        for seq in 0..MESSAGES {
+                            let mut buff = id.to_le_bytes().to_vec();
+                            buff.extend_from_slice(&seq.to_le_bytes());
+                            match peer.send_all(buff).await {
+                                Ok(()) => {
+                                    assert!(!failed, "send succeeded after disconnect");
+                                    sent.fetch_add(1, Ordering::Relaxed);
+                                }
+                                Err(_) => failed = true,
+                            }
+                            // 读取不可变数据不经过 actor
+                            assert!(peer.addr().ip().is_some());
+                            let _ = peer.stats();
+                        }
+                    }));
+                }
+                let disconnect = {
+                    let peer = peer.clone();
+                    tokio::spawn(async move {
+                        tokio::time::sleep(Duration::from_millis(2)).await;
+                        peer.disconnect().await
+                    })
+                };
+                for task in tasks {
+                    task.await?;
+                }
+                disconnect.await??;
+                assert!(peer.is_disconnect().await?);
+                tx.send((peer.addr(), sent.load(Ordering::Relaxed)))
+                    .unwrap();
+                Ok(())
+            }
+        })
+        .build()
+        .await;
+    tcpserver.start(()).await?;
+
+    let mut clients = Vec::new();
+    for _ in 0..CONNECTIONS {
+        clients.push(tokio::spawn(async move {
+            let mut tcp_stream = TcpStream::connect("127.0.0.1:5577").await?;
+            let addr = tcp_stream.local_addr()?;
+            let mut buff = Vec::new();
+            tcp_stream.read_to_end(&mut buff).await?;
+            assert_eq!(buff.len() % 8, 0);
+            // 每条消息完整,同一个任务的消息保持顺序
+            let mut next = [0; TASKS as usize];
+            for msg in buff.chunks(8) {
+                let id = u32::from_le_bytes(msg[..4].try_into()?) as usize;
+                let seq = u32::from_le_bytes(msg[4..].try_into()?);
+                assert_eq!(seq, next[id]);
+                next[id] += 1;
+            }
+            Ok::<_, anyhow::Error>((addr, buff.len() / 8))
+        }));
+    }
+    let mut received = std::collections::HashMap::new();
+    for client in clients {
+        let (addr, count) = client.await??;
+        received.insert(addr, count);
+    }
+    for _ in 0..CONNECTIONS {
+        let (addr, sent) = rx.recv().await.unwrap();
+        assert_eq!(received[&addr.as_tcp().unwrap()], sent);
+    }
+    Ok(())
+}
@@ -1,6 +1,12 @@
 use anyhow::Result;
 use std::sync::Arc;
-use tcpserver::{Builder, IPeer, ITCPServer};
+use std::time::Duration;
+use tcpserver::{
+    boxed_stream_init, AcceptState, AccessList, Builder, Bytes, BytesMut, ConnectRateLimit,
+    ConnectionHandler, DisconnectReason, HandlerServer, IPeer, ITCPServer, IpCidr, PauseMode,
+    PeerAddr, PeerReader, PeerState, RateLimit, ReadHandler, ReadLoop, ServerState, Sniffer,
+    TCPPeer,
+};
 use tokio::io::{AsyncReadExt, AsyncWriteExt};
 
 #[tokio::test]
@@ -36,12 +42,9 @@ async fn echo_server() -> Result<()> {
         serv: Arc<dyn ITCPServer<()>>,
     }
 
-    unsafe impl Send for Foo {}
-    unsafe impl Sync for Foo {}
-
     impl Foo {
         pub async fn start(&self) -> Result<()> {
-            Ok(self.serv.start_block(()).await?)
+            self.serv.start_block(()).await
         }
     }
     let tcpserver: Arc<dyn ITCPServer<()>> = Builder::new("0.0.0.0:5555")
@@ -67,25 +70,1105 @@ async fn echo_server() -> Result<()> {
 
     let foo_server = Arc::new(Foo { serv: tcpserver });
 
-    foo_server.start().await?;
-    Ok(())
+    tokio::spawn(async move { foo_server.start().await });
+    echo_client("127.0.0.1:5555").await
 }
 
-#[tokio::test]
-async fn echo_client() -> Result<()> {
-    let mut tcp_stream = tokio::net::TcpStream::connect("127.0.0.1:5555").await?;
+async fn echo_client(addr: &str) -> Result<()> {
+    let mut tcp_stream = tokio::net::TcpStream::connect(addr).await?;
     let data = b"12231222222221";
     let mut read = [0; 14];
     for _ in 0..100 {
-        tcp_stream.write(data).await?;
-        let len = tcp_stream.read(&mut read).await?;
-        if len != 0 {
-            assert_eq!(*data, read);
-        } else {
-            println!("disconnect");
-            break;
+        tcp_stream.write_all(data).await?;
+        tcp_stream.read_exact(&mut read).await?;
+        assert_eq!(*data, read);
+    }
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn send_file() -> Result<()> {
+    let path = std::env::temp_dir().join("tcpserver_send_file.bin");
+    let data = (0..200_000u32).map(|x| x as u8).collect::<Vec<_>>();
+    std::fs::write(&path, &data)?;
+
+    let file_path = path.clone();
+    let plain = Builder::new("127.0.0.1:5556")
+        .set_stream_init(|tcp_stream| async move { Ok(tcp_stream) })
+        .set_input_event(move |_, peer, _| {
+            let path = file_path.clone();
+            async move {
+                peer.send_file(path, 100, 150_000).await?;
+                Ok(())
+            }
+        })
+        .build()
+        .await;
+    plain.start(()).await?;
+
+    // 包装过的流走缓冲复制
+    let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+    let file_path = path.clone();
+    let buffered = Builder::new("127.0.0.1:5557")
+        .set_stream_init(|tcp_stream| async move { Ok(tokio::io::BufStream::new(tcp_stream)) })
+        .set_input_event(move |_, peer, _| {
+            let path = file_path.clone();
+            let tx = tx.clone();
+            async move {
+                let file = std::fs::File::open(path)?;
+                let mut cursor = file.try_clone()?;
+                peer.send_file(file, 100, 150_000).await?;
+                // 和 sendfile 一样不移动文件的读写位置
+                tx.send(std::io::Seek::stream_position(&mut cursor)?)
+                    .unwrap();
+                Ok(())
+            }
+        })
+        .build()
+        .await;
+    buffered.start(()).await?;
+
+    for addr in ["127.0.0.1:5556", "127.0.0.1:5557"] {
+        let mut tcp_stream = tokio::net::TcpStream::connect(addr).await?;
+        let mut read = Vec::new();
+        tcp_stream.read_to_end(&mut read).await?;
+        assert_eq!(read, data[100..150_100]);
+    }
+    assert_eq!(rx.recv().await.unwrap(), 0);
+    Ok(())
+}
+
+#[tokio::test]
+async fn send_file_cancel() -> Result<()> {
+    // 稀疏文件,远大于 socket 缓冲
+    let path = std::env::temp_dir().join("tcpserver_send_file_cancel.bin");
+    std::fs::File::create(&path)?.set_len(256 << 20)?;
+
+    let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+    let file_path = path.clone();
+    let tcpserver = Builder::new("127.0.0.1:5585")
+        .set_stream_init(|tcp_stream| async move { Ok(tcp_stream) })
+        .set_input_event(move |_, peer, _| {
+            let path = file_path.clone();
+            let tx = tx.clone();
+            async move {
+                // 客户端不读取,发送被取消后连接仍然可以正常断开
+                let send = peer.send_file(path, 0, 256 << 20);
+                let timeout = tokio::time::timeout(Duration::from_millis(200), send).await;
+                tx.send(timeout.is_err()).unwrap();
+                peer.disconnect().await?;
+                tx.send(peer.is_disconnect().await?).unwrap();
+                Ok(())
+            }
+        })
+        .build()
+        .await;
+    tcpserver.start(()).await?;
+
+    let _tcp_stream = tokio::net::TcpStream::connect("127.0.0.1:5585").await?;
+    assert!(rx.recv().await.unwrap());
+    assert!(rx.recv().await.unwrap());
+    std::fs::remove_file(&path)?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn peer_extensions() -> Result<()> {
+    #[derive(Clone, Debug, PartialEq)]
+    struct UserId(u64);
+
+    let tcpserver = Builder::new("127.0.0.1:5558")
+        .set_stream_init(|tcp_stream| async move { Ok(tcp_stream) })
+        .set_input_event(|mut reader, peer, _| async move {
+            assert_eq!(peer.ext::<UserId>().await, None);
+            let id = reader.read_u64().await?;
+            assert_eq!(peer.set_ext(UserId(id)).await, None);
+            let UserId(id) = peer.ext::<UserId>().await.unwrap();
+            peer.send_all(id.to_be_bytes().to_vec()).await?;
+            assert_eq!(peer.remove_ext::<UserId>().await, Some(UserId(id)));
+            assert_eq!(peer.ext::<UserId>().await, None);
+            Ok(())
+        })
+        .build()
+        .await;
+    tcpserver.start(()).await?;
+
+    let mut tcp_stream = tokio::net::TcpStream::connect("127.0.0.1:5558").await?;
+    tcp_stream.write_u64(12345).await?;
+    assert_eq!(tcp_stream.read_u64().await?, 12345);
+    Ok(())
+}
+
+#[tokio::test]
+async fn disconnect_event() -> Result<()> {
+    let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+    let tcpserver = Builder::new("127.0.0.1:5559")
+        .set_stream_init(|tcp_stream| async move { Ok(tcp_stream) })
+        .set_input_event(|mut reader, peer, _| async move {
+            match reader.read_u8().await? {
+                0 => Ok(()),
+                1 => Err(anyhow::anyhow!("handler error")),
+                2 => panic!("handler panic"),
+                // 调用 disconnect 之后的错误和 panic 同样上报
+                3 => {
+                    peer.disconnect().await?;
+                    Err(anyhow::anyhow!("error after disconnect"))
+                }
+                4 => {
+                    peer.disconnect().await?;
+                    panic!("panic after disconnect")
+                }
+                _ => {
+                    peer.disconnect().await?;
+                    Ok(())
+                }
+            }
+        })
+        .set_disconnect_event(move |_, reason, token: u32| {
+            let tx = tx.clone();
+            async move {
+                let reason = match reason {
+                    DisconnectReason::ClientClosed => "closed".to_string(),
+                    DisconnectReason::HandlerError(err) => err.to_string(),
+                    DisconnectReason::HandlerPanic(msg) => msg,
+                    DisconnectReason::Kicked(None) => "kicked".to_string(),
+                    other => format!("{:?}", other),
+                };
+                tx.send((reason, token)).unwrap();
+            }
+        })
+        .build()
+        .await;
+    tcpserver.start(7).await?;
+
+    for (cmd, expect) in [
+        (0u8, "closed"),
+        (1, "handler error"),
+        (2, "handler panic"),
+        (3, "error after disconnect"),
+        (4, "panic after disconnect"),
+        (5, "kicked"),
+    ] {
+        let mut tcp_stream = tokio::net::TcpStream::connect("127.0.0.1:5559").await?;
+        tcp_stream.write_u8(cmd).await?;
+        let (reason, token) = rx.recv().await.unwrap();
+        assert_eq!(reason, expect);
+        assert_eq!(token, 7);
+    }
+    assert_eq!(tcpserver.panic_count().await, 2);
+    Ok(())
+}
+
+#[tokio::test]
+async fn panic_handler() -> Result<()> {
+    let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+    let tcpserver = Builder::new("127.0.0.1:5560")
+        .set_stream_init(|tcp_stream| async move { Ok(tcp_stream) })
+        .set_input_event(|mut reader, _, _| async move {
+            reader.read_u8().await?;
+            panic!("boom");
+        })
+        .set_panic_handler(move |addr, msg| {
+            tx.send((addr.clone(), msg.to_string())).unwrap();
+        })
+        .build()
+        .await;
+    tcpserver.start(()).await?;
+
+    let mut tcp_stream = tokio::net::TcpStream::connect("127.0.0.1:5560").await?;
+    tcp_stream.write_u8(1).await?;
+    let (addr, msg) = rx.recv().await.unwrap();
+    assert_eq!(addr, tcp_stream.local_addr()?);
+    assert_eq!(msg, "boom");
+    // panic 之后连接仍然会被关闭
+    assert_eq!(tcp_stream.read(&mut [0; 1]).await?, 0);
+    assert_eq!(tcpserver.panic_count().await, 1);
+    Ok(())
+}
+
+#[tokio::test]
+async fn disconnect_event_panic() -> Result<()> {
+    let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+    let tcpserver = Builder::new("127.0.0.1:5586")
+        .set_stream_init(|tcp_stream| async move { Ok(tcp_stream) })
+        .set_input_event(|_, _, _| async move { Ok(()) })
+        .set_disconnect_event(|_, _, _| async move {
+            panic!("disconnect boom");
+        })
+        .set_panic_handler(move |_, msg| {
+            tx.send(msg.to_string()).unwrap();
+        })
+        .build()
+        .await;
+    tcpserver.start(()).await?;
+
+    for _ in 0..2 {
+        let mut tcp_stream = tokio::net::TcpStream::connect("127.0.0.1:5586").await?;
+        assert_eq!(tcp_stream.read(&mut [0; 1]).await?, 0);
+        assert_eq!(rx.recv().await.unwrap(), "disconnect boom");
+    }
+    assert_eq!(tcpserver.panic_count().await, 2);
+    Ok(())
+}
+
+#[tokio::test]
+async fn peer_stats() -> Result<()> {
+    let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+    let tcpserver = Builder::new("127.0.0.1:5564")
+        .set_stream_init(|tcp_stream| async move { Ok(tcp_stream) })
+        .set_input_event(move |mut reader, peer, _| {
+            let tx = tx.clone();
+            async move {
+                let mut buff = [0; 10];
+                reader.read_exact(&mut buff).await?;
+                peer.send_all(b"abc".to_vec()).await?;
+                peer.send_all_ref(b"defg").await?;
+                tx.send(peer.stats()).unwrap();
+                Ok(())
+            }
+        })
+        .build()
+        .await;
+    tcpserver.start(()).await?;
+
+    let mut tcp_stream = tokio::net::TcpStream::connect("127.0.0.1:5564").await?;
+    tcp_stream.write_all(b"0123456789").await?;
+    let stats = rx.recv().await.unwrap();
+    assert_eq!(stats.bytes_received, 10);
+    assert_eq!(stats.bytes_sent, 7);
+    assert_eq!(stats.messages_sent, 2);
+    assert_eq!(stats.queue_depth, 0);
+    assert!(stats.last_active >= stats.connected_at);
+    Ok(())
+}
+
+#[tokio::test]
+async fn rate_limit() -> Result<()> {
+    // 每个连接发送 50000 B/s,超出一秒容量的 10000 字节需要等待约 200ms
+    let egress = Builder::new("127.0.0.1:5565")
+        .set_stream_init(|tcp_stream| async move { Ok(tcp_stream) })
+        .set_peer_egress_limit(RateLimit::bytes(50_000))
+        .set_input_event(|mut reader, peer, _| async move {
+            let unlimited = reader.read_u8().await? == 1;
+            if unlimited {
+                peer.set_egress_limit(RateLimit::default());
+            }
+            for _ in 0..6 {
+                peer.send_all(vec![0; 10_000]).await?;
+            }
+            Ok(())
+        })
+        .build()
+        .await;
+    egress.start(()).await?;
+
+    for (unlimited, limited) in [(0u8, true), (1, false)] {
+        let mut tcp_stream = tokio::net::TcpStream::connect("127.0.0.1:5565").await?;
+        tcp_stream.write_u8(unlimited).await?;
+        let start = std::time::Instant::now();
+        let mut buff = Vec::new();
+        tcp_stream.read_to_end(&mut buff).await?;
+        assert_eq!(buff.len(), 60_000);
+        assert_eq!(
+            start.elapsed() >= std::time::Duration::from_millis(150),
+            limited
+        );
+    }
+
+    // 接收方向按读取次数计数,全局每秒 2 次读取,第 3 次读取需要等待约 500ms
+    let ingress = Builder::new("127.0.0.1:5566")
+        .set_stream_init(|tcp_stream| async move { Ok(tcp_stream) })
+        .set_global_ingress_limit(RateLimit::messages(2))
+        .set_input_event(|mut reader, peer, _| async move {
+            let start = std::time::Instant::now();
+            for _ in 0..4 {
+                reader.read_u8().await?;
+            }
+            peer.send_all(start.elapsed().as_millis().to_be_bytes().to_vec())
+                .await?;
+            Ok(())
+        })
+        .build()
+        .await;
+    ingress.start(()).await?;
+
+    let mut tcp_stream = tokio::net::TcpStream::connect("127.0.0.1:5566").await?;
+    for _ in 0..4 {
+        tcp_stream.write_u8(1).await?;
+        tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+    }
+    assert!(tcp_stream.read_u128().await? >= 400);
+    Ok(())
+}
+
+#[tokio::test]
+async fn rate_limit_send_file() -> Result<()> {
+    let path = std::env::temp_dir().join("tcpserver_rate_limit_send_file.bin");
+    std::fs::write(&path, vec![1u8; 100_000])?;
+
+    // 限速时文件分块发送,等待期间 actor 不被占用
+    let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+    let file_path = path.clone();
+    let tcpserver = Builder::new("127.0.0.1:5587")
+        .set_stream_init(|tcp_stream| async move { Ok(tcp_stream) })
+        .set_peer_egress_limit(RateLimit::bytes(50_000))
+        .set_input_event(move |_, peer, _| {
+            let path = file_path.clone();
+            let tx = tx.clone();
+            async move {
+                let sending = peer.clone();
+                let send = tokio::spawn(async move { sending.send_file(path, 0, 100_000).await });
+                tokio::time::sleep(Duration::from_millis(50)).await;
+                let ext = tokio::time::timeout(Duration::from_millis(100), peer.ext::<u32>()).await;
+                tx.send(ext.is_ok()).unwrap();
+                assert_eq!(send.await??, 100_000);
+                let stats = peer.stats();
+                tx.send(stats.bytes_sent == 100_000 && stats.messages_sent == 1)
+                    .unwrap();
+                Ok(())
+            }
+        })
+        .build()
+        .await;
+    tcpserver.start(()).await?;
+
+    let mut tcp_stream = tokio::net::TcpStream::connect("127.0.0.1:5587").await?;
+    let start = std::time::Instant::now();
+    let mut buff = Vec::new();
+    tcp_stream.read_to_end(&mut buff).await?;
+    assert_eq!(buff.len(), 100_000);
+    assert!(start.elapsed() >= Duration::from_millis(800));
+    assert!(rx.recv().await.unwrap());
+    assert!(rx.recv().await.unwrap());
+    std::fs::remove_file(&path)?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn connect_rate_limit() -> Result<()> {
+    let tcpserver = Builder::new("127.0.0.1:5567")
+        .set_stream_init(|tcp_stream| async move { Ok(tcp_stream) })
+        .set_connect_rate_limit(
+            ConnectRateLimit::new(1)
+                .burst(2)
+                .ban(2, std::time::Duration::from_secs(60)),
+        )
+        .set_input_event(|_, peer, _| async move {
+            peer.send_all_ref(b"1").await?;
+            Ok(())
+        })
+        .build()
+        .await;
+    tcpserver.start(()).await?;
+
+    async fn accepted() -> Result<bool> {
+        let mut tcp_stream = tokio::net::TcpStream::connect("127.0.0.1:5567").await?;
+        let mut buff = Vec::new();
+        tcp_stream.read_to_end(&mut buff).await?;
+        Ok(buff == b"1")
+    }
+
+    let localhost = "127.0.0.1".parse()?;
+    assert!(accepted().await?);
+    assert!(accepted().await?);
+    assert!(!accepted().await?);
+    assert!(tcpserver.banned().await.is_empty());
+    assert!(!accepted().await?);
+    let banned = tcpserver.banned().await;
+    assert_eq!(banned.len(), 1);
+    assert_eq!(banned[0].0, localhost);
+
+    assert!(tcpserver.unban(localhost).await);
+    tokio::time::sleep(std::time::Duration::from_millis(1100)).await;
+    assert!(accepted().await?);
+
+    tcpserver.ban(localhost, None).await;
+    tokio::time::sleep(std::time::Duration::from_millis(1100)).await;
+    assert!(!accepted().await?);
+    Ok(())
+}
+
+#[tokio::test]
+async fn access_list() -> Result<()> {
+    let acl = AccessList::parse(
+        "# localhost only
+        default deny
+        deny 127.0.0.2
+        allow 127.0.0.0/8
+        allow ::1/128",
+    )?;
+    assert!(acl.is_allowed("127.0.0.1".parse()?));
+    assert!(!acl.is_allowed("127.0.0.2".parse()?));
+    assert!(acl.is_allowed("::ffff:127.0.0.3".parse()?));
+    assert!(!acl.is_allowed("10.0.0.1".parse()?));
+    assert!(AccessList::parse("allow 10.0.0.0/33").is_err());
+
+    // IPv4 映射地址两边都按 IPv4 处理
+    let mapped = IpCidr::new("::ffff:10.1.0.0".parse()?, 112).unwrap();
+    assert_eq!(mapped, IpCidr::new("10.1.0.0".parse()?, 16).unwrap());
+    assert!(mapped.contains("10.1.2.3".parse()?));
+    assert!(mapped.contains("::ffff:10.1.2.3".parse()?));
+    assert!(!mapped.contains("10.2.0.1".parse()?));
+    let any = IpCidr::new("::".parse()?, 0).unwrap();
+    assert!(any.contains("10.2.0.1".parse()?));
+    let mapped_acl = AccessList::parse("default deny\nallow ::ffff:192.168.0.0/120")?;
+    assert!(mapped_acl.is_allowed("192.168.0.7".parse()?));
+    assert!(!mapped_acl.is_allowed("192.168.1.7".parse()?));
+
+    let path = std::env::temp_dir().join("tcpserver_acl.txt");
+    std::fs::write(&path, "deny 127.0.0.1\n")?;
+    let tcpserver = Builder::new("127.0.0.1:5568")
+        .set_stream_init(|tcp_stream| async move { Ok(tcp_stream) })
+        .set_access_list(AccessList::from_file(&path)?)
+        .set_input_event(|_, peer, _| async move {
+            peer.send_all_ref(b"1").await?;
+            Ok(())
+        })
+        .build()
+        .await;
+    tcpserver.start(()).await?;
+
+    async fn accepted() -> Result<bool> {
+        let mut tcp_stream = tokio::net::TcpStream::connect("127.0.0.1:5568").await?;
+        let mut buff = Vec::new();
+        tcp_stream.read_to_end(&mut buff).await?;
+        Ok(buff == b"1")
+    }
+
+    assert!(!accepted().await?);
+    tcpserver.set_access_list(Some(acl)).await;
+    assert!(accepted().await?);
+    tcpserver
+        .set_access_list(Some(
+            AccessList::new().deny(IpCidr::new("127.0.0.0".parse()?, 8).unwrap()),
+        ))
+        .await;
+    assert!(!accepted().await?);
+    tcpserver.set_access_list(None).await;
+    assert!(accepted().await?);
+    Ok(())
+}
+
+#[tokio::test]
+async fn dyn_peer() -> Result<()> {
+    // dyn IDynPeer 的方法不需要导入 trait,避免和 IPeer 同名方法冲突
+    type Registry = Arc<std::sync::Mutex<Vec<Arc<dyn tcpserver::IDynPeer>>>>;
+    let registry = Registry::default();
+    let plain = Builder::new("127.0.0.1:5574")
+        .set_stream_init(|tcp_stream| async move { Ok(tcp_stream) })
+        .set_input_event(|mut reader, peer, registry: Registry| async move {
+            registry.lock().unwrap().push(peer);
+            while reader.read_u8().await.is_ok() {}
+            Ok(())
+        })
+        .build()
+        .await;
+    plain.start(registry.clone()).await?;
+    let sniffer = Arc::new(Sniffer::new());
+    let sniffed = Builder::new("127.0.0.1:5575")
+        .set_stream_init(move |tcp_stream| {
+            let sniffer = sniffer.clone();
+            async move { sniffer.accept(tcp_stream).await }
+        })
+        .set_input_event(|mut reader, peer, registry: Registry| async move {
+            registry.lock().unwrap().push(peer);
+            while reader.read_u8().await.is_ok() {}
+            Ok(())
+        })
+        .build()
+        .await;
+    sniffed.start(registry.clone()).await?;
+
+    let mut first = tokio::net::TcpStream::connect("127.0.0.1:5574").await?;
+    let mut second = tokio::net::TcpStream::connect("127.0.0.1:5575").await?;
+    second.write_all(b"hello").await?;
+    while registry.lock().unwrap().len() < 2 {
+        tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+    }
+
+    let peers = registry.lock().unwrap().clone();
+    for peer in peers.iter() {
+        peer.send_all(Bytes::from_static(b"news")).await?;
+    }
+    let mut buff = [0; 4];
+    first.read_exact(&mut buff).await?;
+    assert_eq!(&buff, b"news");
+    second.read_exact(&mut buff).await?;
+    assert_eq!(&buff, b"news");
+    for peer in peers.iter() {
+        peer.disconnect().await?;
+        assert!(peer.is_disconnect().await?);
+    }
+    assert_eq!(first.read(&mut buff).await?, 0);
+    Ok(())
+}
+
+#[tokio::test]
+async fn connection_handler() -> Result<()> {
+    use aqueue::Actor;
+    use tokio::net::TcpStream;
+
+    struct Echo {
+        tx: tokio::sync::mpsc::UnboundedSender<(String, u32)>,
+    }
+
+    impl ConnectionHandler<TcpStream, u32> for Echo {
+        fn on_connect(&self, addr: &PeerAddr) -> bool {
+            addr.ip().is_some_and(|ip| ip.is_loopback())
+        }
+
+        async fn handle(
+            &self,
+            mut reader: PeerReader<TcpStream>,
+            peer: Arc<TCPPeer<TcpStream>>,
+            token: u32,
+        ) -> anyhow::Result<()> {
+            let mut buff = [0; 64];
+            let len = reader.read(&mut buff).await?;
+            peer.send_all_ref(&buff[..len]).await?;
+            peer.send_all(token.to_le_bytes().to_vec()).await?;
+            Ok(())
         }
+
+        async fn on_disconnect(
+            &self,
+            _peer: Arc<TCPPeer<TcpStream>>,
+            reason: DisconnectReason,
+            token: u32,
+        ) {
+            self.tx.send((format!("{:?}", reason), token)).unwrap();
+        }
+    }
+
+    // 服务器类型可以写出来,保存在结构体中
+    struct App {
+        server: Arc<Actor<HandlerServer<TcpStream, u32>>>,
     }
 
+    let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+    let app = App {
+        server: Builder::with_handler("127.0.0.1:5576", Echo { tx })
+            .set_stream_init(boxed_stream_init(
+                |tcp_stream| async move { Ok(tcp_stream) },
+            ))
+            .build()
+            .await,
+    };
+    app.server.start(9).await?;
+
+    let mut tcp_stream = TcpStream::connect("127.0.0.1:5576").await?;
+    tcp_stream.write_all(b"hi").await?;
+    let mut buff = Vec::new();
+    tcp_stream.read_to_end(&mut buff).await?;
+    assert_eq!(buff, b"hi\x09\0\0\0");
+    assert_eq!(rx.recv().await.unwrap(), ("ClientClosed".to_string(), 9));
+    Ok(())
+}
+
+#[tokio::test]
+async fn read_loop() -> Result<()> {
+    use tokio::net::TcpStream;
+
+    /// 按行解码,回复大写
+    struct Lines {
+        tx: tokio::sync::mpsc::UnboundedSender<&'static str>,
+    }
+
+    impl ReadHandler<TcpStream, ()> for Lines {
+        async fn on_read(
+            &self,
+            peer: &Arc<TCPPeer<TcpStream>>,
+            buff: &mut BytesMut,
+            _: &(),
+        ) -> anyhow::Result<()> {
+            while let Some(pos) = buff.iter().position(|&b| b == b'\n') {
+                let line = buff.split_to(pos + 1);
+                peer.send_all(line.to_ascii_uppercase()).await?;
+            }
+            Ok(())
+        }
+
+        async fn on_disconnect(
+            &self,
+            _peer: Arc<TCPPeer<TcpStream>>,
+            reason: DisconnectReason,
+            _: (),
+        ) {
+            let reason = match reason {
+                DisconnectReason::ClientClosed => "closed",
+                DisconnectReason::IdleTimeout => "idle",
+                _ => "other",
+            };
+            self.tx.send(reason).unwrap();
+        }
+    }
+
+    let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+    let tcpserver = Builder::with_handler(
+        "127.0.0.1:5578",
+        ReadLoop::new(Lines { tx }).set_idle_timeout(Duration::from_millis(200)),
+    )
+    .set_stream_init(|tcp_stream| async move { Ok(tcp_stream) })
+    .build()
+    .await;
+    tcpserver.start(()).await?;
+
+    let mut tcp_stream = TcpStream::connect("127.0.0.1:5578").await?;
+    for part in [&b"hel"[..], b"lo\nwor", b"ld\n"] {
+        tcp_stream.write_all(part).await?;
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    let mut buff = [0; 12];
+    tcp_stream.read_exact(&mut buff).await?;
+    assert_eq!(&buff, b"HELLO\nWORLD\n");
+    tcp_stream.shutdown().await?;
+    assert_eq!(rx.recv().await.unwrap(), "closed");
+
+    // 不发送数据,空闲超时后被断开
+    let mut tcp_stream = TcpStream::connect("127.0.0.1:5578").await?;
+    assert_eq!(tcp_stream.read(&mut buff).await?, 0);
+    assert_eq!(rx.recv().await.unwrap(), "idle");
+    Ok(())
+}
+
+#[tokio::test]
+async fn half_close() -> Result<()> {
+    type Sender = tokio::sync::mpsc::UnboundedSender<Vec<u8>>;
+    let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+    let tcpserver = Builder::new("127.0.0.1:5579")
+        .set_stream_init(|tcp_stream| async move { Ok(tcp_stream) })
+        .set_input_event(|mut reader, peer, tx: Sender| async move {
+            let mut buff = [0; 3];
+            reader.read_exact(&mut buff).await?;
+            assert_eq!(peer.state(), PeerState::Open);
+            peer.send_all(b"pong".to_vec()).await?;
+            // 回复完毕,发送 FIN,继续读取客户端剩余数据
+            peer.shutdown_write().await?;
+            assert_eq!(peer.state(), PeerState::WriteClosed);
+            assert!(!peer.is_disconnect().await?);
+            assert!(peer.send(b"x".to_vec()).await.is_err());
+            let mut rest = Vec::new();
+            reader.read_to_end(&mut rest).await?;
+            assert_eq!(peer.state(), PeerState::Closed);
+            assert!(peer.is_disconnect().await?);
+            tx.send(rest).unwrap();
+            Ok(())
+        })
+        .set_disconnect_event(|_, reason, tx: Sender| async move {
+            assert!(matches!(reason, DisconnectReason::ClientClosed));
+            tx.send(b"closed".to_vec()).unwrap();
+        })
+        .build()
+        .await;
+    tcpserver.start(tx).await?;
+
+    let mut tcp_stream = tokio::net::TcpStream::connect("127.0.0.1:5579").await?;
+    tcp_stream.write_all(b"req").await?;
+    let mut buff = Vec::new();
+    tcp_stream.read_to_end(&mut buff).await?;
+    assert_eq!(buff, b"pong");
+    // 服务器写端关闭后仍然可以发送
+    tcp_stream.write_all(b"tail").await?;
+    tcp_stream.shutdown().await?;
+    assert_eq!(rx.recv().await.unwrap(), b"tail");
+    assert_eq!(rx.recv().await.unwrap(), b"closed");
+
+    // 客户端先关闭发送方向,服务器仍然可以回复
+    let tcpserver = Builder::new("127.0.0.1:5580")
+        .set_stream_init(|tcp_stream| async move { Ok(tcp_stream) })
+        .set_input_event(|mut reader, peer, _| async move {
+            let mut buff = Vec::new();
+            reader.read_to_end(&mut buff).await?;
+            assert_eq!(peer.state(), PeerState::ReadClosed);
+            assert!(!peer.is_disconnect().await?);
+            peer.send_all(buff).await?;
+            Ok(())
+        })
+        .build()
+        .await;
+    tcpserver.start(()).await?;
+
+    let mut tcp_stream = tokio::net::TcpStream::connect("127.0.0.1:5580").await?;
+    tcp_stream.write_all(b"echo").await?;
+    tcp_stream.shutdown().await?;
+    let mut buff = Vec::new();
+    tcp_stream.read_to_end(&mut buff).await?;
+    assert_eq!(buff, b"echo");
+    Ok(())
+}
+
+#[tokio::test]
+async fn kick() -> Result<()> {
+    let (peer_tx, mut peer_rx) = tokio::sync::mpsc::unbounded_channel();
+    let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+    let read_tx = tx.clone();
+    let tcpserver = Builder::new("127.0.0.1:5581")
+        .set_stream_init(|tcp_stream| async move { Ok(tcp_stream) })
+        .set_input_event(move |mut reader, peer, _| {
+            let peer_tx = peer_tx.clone();
+            let read_tx = read_tx.clone();
+            async move {
+                peer_tx.send(peer).unwrap();
+                // 阻塞在读取上,kick 之后返回错误
+                let err = reader.read_u8().await.unwrap_err();
+                read_tx.send(err.to_string()).unwrap();
+                Err(err.into())
+            }
+        })
+        .set_disconnect_event(move |peer, reason, _| {
+            let tx = tx.clone();
+            async move {
+                assert_eq!(peer.state(), PeerState::Closed);
+                if let DisconnectReason::Kicked(Some(reason)) = reason {
+                    tx.send(reason).unwrap();
+                }
+            }
+        })
+        .build()
+        .await;
+    tcpserver.start(()).await?;
+
+    let mut tcp_stream = tokio::net::TcpStream::connect("127.0.0.1:5581").await?;
+    let peer = peer_rx.recv().await.unwrap();
+    peer.kick("banned", b"bye").await?;
+    assert!(peer.is_disconnect().await?);
+    assert!(peer.send(b"x".to_vec()).await.is_err());
+
+    let mut buff = Vec::new();
+    tcp_stream.read_to_end(&mut buff).await?;
+    assert_eq!(buff, b"bye");
+    assert_eq!(rx.recv().await.unwrap(), "kicked");
+    assert_eq!(rx.recv().await.unwrap(), "banned");
+    Ok(())
+}
+
+#[tokio::test]
+async fn kick_stalled_client() -> Result<()> {
+    let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+    let tcpserver = Builder::new("127.0.0.1:5589")
+        .set_stream_init(|tcp_stream| async move { Ok(tcp_stream) })
+        .set_input_event(move |mut reader, peer, _| {
+            let tx = tx.clone();
+            async move {
+                // 客户端不读取,发送阻塞在写满的缓冲上
+                let sending = peer.clone();
+                let send = tokio::spawn(async move { sending.send_all(vec![0; 64 << 20]).await });
+                tokio::time::sleep(Duration::from_millis(100)).await;
+                let start = std::time::Instant::now();
+                let kicked = peer.kick("stalled", b"bye").await;
+                tx.send(start.elapsed() < Duration::from_secs(3)).unwrap();
+                tx.send(kicked.is_err()).unwrap();
+                tx.send(send.await?.is_err()).unwrap();
+                tx.send(reader.read_u8().await.is_err()).unwrap();
+                Ok(())
+            }
+        })
+        .build()
+        .await;
+    tcpserver.start(()).await?;
+
+    let _tcp_stream = tokio::net::TcpStream::connect("127.0.0.1:5589").await?;
+    for _ in 0..4 {
+        assert!(rx.recv().await.unwrap());
+    }
+    Ok(())
+}
+
+#[tokio::test]
+async fn pause_accept() -> Result<()> {
+    let tcpserver = Builder::new("127.0.0.1:5582")
+        .set_stream_init(|tcp_stream| async move { Ok(tcp_stream) })
+        .set_input_event(|mut reader, peer, _| async move {
+            let mut buff = [0; 16];
+            loop {
+                let len = reader.read(&mut buff).await?;
+                if len == 0 {
+                    return Ok(());
+                }
+                peer.send_all(buff[..len].to_vec()).await?;
+            }
+        })
+        .build()
+        .await;
+    tcpserver.start(()).await?;
+
+    async fn echo(tcp_stream: &mut tokio::net::TcpStream) -> Result<bool> {
+        tcp_stream.write_all(b"ping").await?;
+        let mut buff = [0; 4];
+        let read = tcp_stream.read_exact(&mut buff);
+        Ok(tokio::time::timeout(Duration::from_millis(100), read)
+            .await
+            .is_ok())
+    }
+
+    let mut first = tokio::net::TcpStream::connect("127.0.0.1:5582").await?;
+    assert!(echo(&mut first).await?);
+    assert_eq!(tcpserver.accept_state().await, AcceptState::Accepting);
+
+    // 保持监听,新连接在 backlog 中等待
+    tcpserver.pause_accept(PauseMode::KeepListening).await;
+    assert_eq!(
+        tcpserver.accept_state().await,
+        AcceptState::Paused(PauseMode::KeepListening)
+    );
+    let mut queued = tokio::net::TcpStream::connect("127.0.0.1:5582").await?;
+    assert!(!echo(&mut queued).await?);
+    assert!(echo(&mut first).await?);
+    tcpserver.resume_accept().await;
+    let mut buff = [0; 4];
+    queued.read_exact(&mut buff).await?;
+    assert_eq!(&buff, b"ping");
+
+    // 关闭监听,新连接被拒绝
+    tcpserver.pause_accept(PauseMode::CloseListener).await;
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    assert!(tokio::net::TcpStream::connect("127.0.0.1:5582")
+        .await
+        .is_err());
+    assert!(echo(&mut first).await?);
+    tcpserver.resume_accept().await;
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    let mut reopened = tokio::net::TcpStream::connect("127.0.0.1:5582").await?;
+    assert!(echo(&mut reopened).await?);
+    Ok(())
+}
+
+#[tokio::test]
+async fn server_lifecycle() -> Result<()> {
+    let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+    let tcpserver = Builder::new("127.0.0.1:5583")
+        .set_stream_init(|tcp_stream| async move { Ok(tcp_stream) })
+        .set_input_event(|mut reader, peer, _| async move {
+            let mut buff = [0; 16];
+            loop {
+                let len = reader.read(&mut buff).await?;
+                if len == 0 {
+                    return Ok(());
+                }
+                peer.send_all(buff[..len].to_vec()).await?;
+            }
+        })
+        .set_disconnect_event(move |_, reason, _| {
+            let tx = tx.clone();
+            async move {
+                tx.send(format!("{:?}", reason)).unwrap();
+            }
+        })
+        .build()
+        .await;
+    assert!(tcpserver.state().await.is_stopped());
+    let mut state = tcpserver.watch_state().await;
+    let join = tcpserver.start(()).await?;
+    state.wait_for(ServerState::is_running).await?;
+
+    let mut closing = tokio::net::TcpStream::connect("127.0.0.1:5583").await?;
+    let mut idle = tokio::net::TcpStream::connect("127.0.0.1:5583").await?;
+    for tcp_stream in [&mut closing, &mut idle] {
+        tcp_stream.write_all(b"ping").await?;
+        let mut buff = [0; 4];
+        tcp_stream.read_exact(&mut buff).await?;
+    }
+    assert_eq!(tcpserver.connection_count().await, 2);
+
+    let shutdown = {
+        let tcpserver = tcpserver.clone();
+        tokio::spawn(async move { tcpserver.shutdown(Duration::from_millis(200)).await })
+    };
+    state
+        .wait_for(|state| matches!(state, ServerState::Draining))
+        .await?;
+    tokio::time::sleep(Duration::from_millis(20)).await;
+    assert!(tokio::net::TcpStream::connect("127.0.0.1:5583")
+        .await
+        .is_err());
+
+    // 等待期间正常关闭的连接
+    closing.shutdown().await?;
+    assert_eq!(rx.recv().await.unwrap(), "ClientClosed");
+    // 超时后被中止的连接
+    assert_eq!(rx.recv().await.unwrap(), "ServerShutdown");
+    let mut buff = Vec::new();
+    idle.read_to_end(&mut buff).await?;
+
+    shutdown.await?;
+    let stopped = tcpserver.state().await;
+    assert!(stopped.is_stopped());
+    assert!(stopped.error().is_none());
+    assert_eq!(tcpserver.connection_count().await, 0);
+    join.await??;
+    Ok(())
+}
+
+#[tokio::test]
+async fn shutdown_hung_disconnect() -> Result<()> {
+    let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+    let tcpserver = Builder::new("127.0.0.1:5590")
+        .set_stream_init(|tcp_stream| async move { Ok(tcp_stream) })
+        .set_input_event(|mut reader, _, _| async move {
+            let mut buff = [0; 16];
+            while reader.read(&mut buff).await? > 0 {}
+            Ok(())
+        })
+        .set_disconnect_event(move |_, reason, _| {
+            let tx = tx.clone();
+            async move {
+                tx.send(format!("{:?}", reason)).unwrap();
+                // 断线事件一直不结束
+                std::future::pending::<()>().await;
+            }
+        })
+        .build()
+        .await;
+    let join = tcpserver.start(()).await?;
+    let mut state = tcpserver.watch_state().await;
+    state.wait_for(ServerState::is_running).await?;
+
+    let _tcp_stream = tokio::net::TcpStream::connect("127.0.0.1:5590").await?;
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    assert_eq!(tcpserver.connection_count().await, 1);
+
+    // 超过等待时间和断线处理时间后强制结束
+    tokio::time::timeout(
+        Duration::from_secs(5),
+        tcpserver.shutdown(Duration::from_millis(100)),
+    )
+    .await?;
+    assert_eq!(rx.recv().await.unwrap(), "ServerShutdown");
+    assert!(tcpserver.state().await.is_stopped());
+    assert_eq!(tcpserver.connection_count().await, 0);
+    join.await??;
+    Ok(())
+}
+
+#[tokio::test]
+async fn shutdown_slow_disconnect() -> Result<()> {
+    let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+    let tcpserver = Builder::new("127.0.0.1:5592")
+        .set_stream_init(|tcp_stream| async move { Ok(tcp_stream) })
+        .set_input_event(|mut reader, _, _| async move {
+            let mut buff = [0; 16];
+            while reader.read(&mut buff).await? > 0 {}
+            Ok(())
+        })
+        .set_disconnect_event(move |_, reason, _| {
+            let tx = tx.clone();
+            async move {
+                tokio::time::sleep(Duration::from_millis(400)).await;
+                tx.send(format!("{:?}", reason)).unwrap();
+            }
+        })
+        .build()
+        .await;
+    let join = tcpserver.start(()).await?;
+    let mut state = tcpserver.watch_state().await;
+    state.wait_for(ServerState::is_running).await?;
+
+    let mut tcp_stream = tokio::net::TcpStream::connect("127.0.0.1:5592").await?;
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    let shutdown = {
+        let tcpserver = tcpserver.clone();
+        tokio::spawn(async move { tcpserver.shutdown(Duration::from_millis(200)).await })
+    };
+    // 等待时间内开始的断线事件,超过等待时间后仍然执行完
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    tcp_stream.shutdown().await?;
+    shutdown.await?;
+    assert_eq!(rx.try_recv()?, "ClientClosed");
+    assert!(tcpserver.state().await.is_stopped());
+    join.await??;
+    Ok(())
+}
+
+#[tokio::test]
+async fn accept_task_panic() -> Result<()> {
+    use tokio::net::TcpStream;
+
+    struct Panic;
+
+    impl ConnectionHandler<TcpStream, ()> for Panic {
+        fn on_connect(&self, _addr: &PeerAddr) -> bool {
+            panic!("on_connect panic");
+        }
+
+        async fn handle(
+            &self,
+            _reader: PeerReader<TcpStream>,
+            _peer: Arc<TCPPeer<TcpStream>>,
+            _token: (),
+        ) -> anyhow::Result<()> {
+            Ok(())
+        }
+    }
+
+    let tcpserver = Builder::with_handler("127.0.0.1:5591", Panic)
+        .set_stream_init(boxed_stream_init(
+            |tcp_stream| async move { Ok(tcp_stream) },
+        ))
+        .build()
+        .await;
+    let mut state = tcpserver.watch_state().await;
+    let join = tcpserver.start(()).await?;
+    state.wait_for(ServerState::is_running).await?;
+
+    // accept 任务 panic 后进入 Stopped 并带上错误
+    let _tcp_stream = TcpStream::connect("127.0.0.1:5591").await?;
+    let stopped = state.wait_for(ServerState::is_stopped).await?.clone();
+    assert!(stopped
+        .error()
+        .unwrap()
+        .to_string()
+        .contains("on_connect panic"));
+    assert!(join.await?.is_err());
+    assert_eq!(tcpserver.panic_count().await, 1);
+    tokio::time::timeout(
+        Duration::from_secs(1),
+        tcpserver.shutdown(Duration::from_millis(100)),
+    )
+    .await?;
+
+    // 中止返回的 JoinHandle 同样进入 Stopped
+    let join = tcpserver.start(()).await?;
+    state.wait_for(ServerState::is_running).await?;
+    join.abort();
+    let stopped = state.wait_for(ServerState::is_stopped).await?.clone();
+    assert_eq!(
+        stopped.error().unwrap().kind(),
+        std::io::ErrorKind::Interrupted
+    );
+    tokio::time::timeout(
+        Duration::from_secs(1),
+        tcpserver.shutdown(Duration::from_millis(100)),
+    )
+    .await?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn restart_server() -> Result<()> {
+    let tcpserver = Builder::new("127.0.0.1:5584")
+        .set_connect_event(|addr| addr.ip().is_some())
+        .set_stream_init(|tcp_stream| async move { Ok(tcp_stream) })
+        .set_input_event(|_, peer, token: u8| async move {
+            peer.send_all(vec![token]).await?;
+            Ok(())
+        })
+        .build()
+        .await;
+
+    for token in 1..=3u8 {
+        let join = tcpserver.start(token).await?;
+        // 运行中不能重复启动
+        assert!(tcpserver.start(token).await.is_err());
+        let mut tcp_stream = tokio::net::TcpStream::connect("127.0.0.1:5584").await?;
+        assert_eq!(tcp_stream.read_u8().await?, token);
+
+        tcpserver.shutdown(Duration::from_millis(100)).await;
+        join.await??;
+        assert!(tcpserver.state().await.is_stopped());
+        assert!(tokio::net::TcpStream::connect("127.0.0.1:5584")
+            .await
+            .is_err());
+    }
     Ok(())
 }
@@ -0,0 +1,76 @@
+#![cfg(feature = "tracing")]
+
+use anyhow::Result;
+use std::io::Write;
+use std::sync::{Arc, Mutex};
+use tcpserver::{Builder, IPeer, ITCPServer};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+#[derive(Clone, Default)]
+struct Output(Arc<Mutex<Vec<u8>>>);
+
+impl Write for Output {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.lock().unwrap().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+#[tokio::test]
+async fn connection_span() -> Result<()> {
+    let output = Output::default();
+    let writer = output.clone();
+    tracing_subscriber::fmt()
+        .with_ansi(false)
+        .with_max_level(tracing::Level::TRACE)
+        .with_span_events(tracing_subscriber::fmt::format::FmtSpan::NEW)
+        .with_writer(move || writer.clone())
+        .init();
+
+    let path = std::env::temp_dir().join("tcpserver_tracing.bin");
+    std::fs::write(&path, b"0123456789")?;
+    let file_path = path.clone();
+    let tcpserver = Builder::new("127.0.0.1:5563")
+        .set_stream_init(|tcp_stream| async move { Ok(tcp_stream) })
+        .set_input_event(move |mut reader, peer, _| {
+            let path = file_path.clone();
+            async move {
+                let value = reader.read_u8().await?;
+                tracing::info!(value, "handler event");
+                peer.send_all(vec![value]).await?;
+                peer.send_file(path, 2, 3).await?;
+                Ok(())
+            }
+        })
+        .build()
+        .await;
+    tcpserver.start(()).await?;
+
+    let mut tcp_stream = tokio::net::TcpStream::connect("127.0.0.1:5563").await?;
+    tcp_stream.write_u8(9).await?;
+    assert_eq!(tcp_stream.read_u8().await?, 9);
+    let mut buff = [0; 3];
+    tcp_stream.read_exact(&mut buff).await?;
+    assert_eq!(&buff, b"234");
+
+    let peer = tcp_stream.local_addr()?;
+    let output = String::from_utf8(output.0.lock().unwrap().clone())?;
+    let event = output
+        .lines()
+        .find(|line| line.contains("handler event"))
+        .unwrap();
+    assert!(event.contains(&format!("connection{{peer={} conn_id=", peer)));
+    assert!(event.contains("listener=127.0.0.1:5563"));
+    assert!(event.contains(":input_event:"));
+    assert!(output
+        .lines()
+        .any(|line| line.contains("input_event:send_all{len=1}")));
+    assert!(output
+        .lines()
+        .any(|line| line.contains("send_file{offset=2 len=3}")));
+    Ok(())
+}
@@ -0,0 +1,130 @@
+use anyhow::Result;
+use std::time::Duration;
+use tcpserver::{Builder, ITCPServer, ITransportPeer, IUdpServer, UdpBuilder};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::UdpSocket;
+
+/// TCP 和 UDP 共用的处理代码
+async fn greet<P: ITransportPeer>(peer: &P) -> tcpserver::error::Result<usize> {
+    peer.send(format!("hello {}", peer.addr()).into_bytes())
+        .await
+}
+
+#[tokio::test]
+async fn udp_echo() -> Result<()> {
+    let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+    let udpserver = UdpBuilder::new("127.0.0.1:5569")
+        .set_idle_timeout(Duration::from_millis(200))
+        .set_input_event(move |mut reader, peer, _| {
+            let tx = tx.clone();
+            async move {
+                greet(&*peer).await?;
+                while let Some(data) = reader.recv().await {
+                    peer.send_ref(&data).await?;
+                }
+                tx.send(peer.socket_addr()).unwrap();
+                Ok(())
+            }
+        })
+        .build()
+        .await;
+    udpserver.start(()).await?;
+
+    let client = UdpSocket::bind("127.0.0.1:0").await?;
+    client.connect("127.0.0.1:5569").await?;
+    client.send(b"ping").await?;
+    let mut buff = [0; 64];
+    let len = client.recv(&mut buff).await?;
+    assert_eq!(
+        &buff[..len],
+        format!("hello udp:{}", client.local_addr()?).as_bytes()
+    );
+    let len = client.recv(&mut buff).await?;
+    assert_eq!(&buff[..len], b"ping");
+    assert_eq!(udpserver.session_count().await, 1);
+
+    // 空闲超时后虚拟连接结束
+    assert_eq!(rx.recv().await.unwrap(), client.local_addr()?);
+    assert_eq!(udpserver.session_count().await, 0);
+
+    // 再次发送会创建新的虚拟连接
+    client.send(b"again").await?;
+    let len = client.recv(&mut buff).await?;
+    assert!(buff[..len].starts_with(b"hello udp:"));
+    let len = client.recv(&mut buff).await?;
+    assert_eq!(&buff[..len], b"again");
+    Ok(())
+}
+
+#[tokio::test]
+async fn udp_session_limits() -> Result<()> {
+    struct Dropped(tokio::sync::mpsc::UnboundedSender<()>);
+    impl Drop for Dropped {
+        fn drop(&mut self) {
+            let _ = self.0.send(());
+        }
+    }
+
+    let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+    let udpserver = UdpBuilder::new("127.0.0.1:5588")
+        .set_idle_timeout(Duration::from_millis(200))
+        .set_max_sessions(1)
+        .set_input_event(move |_, peer, _| {
+            let dropped = Dropped(tx.clone());
+            async move {
+                // 不调用 recv,由服务器关闭空闲连接并中止任务
+                let _dropped = dropped;
+                peer.send_ref(b"hi").await?;
+                std::future::pending::<()>().await;
+                Ok(())
+            }
+        })
+        .build()
+        .await;
+    udpserver.start(()).await?;
+
+    let first = UdpSocket::bind("127.0.0.1:0").await?;
+    first.connect("127.0.0.1:5588").await?;
+    first.send(b"1").await?;
+    let mut buff = [0; 16];
+    assert_eq!(first.recv(&mut buff).await?, 2);
+
+    // 超过最大连接数的新地址被丢弃
+    let second = UdpSocket::bind("127.0.0.1:0").await?;
+    second.connect("127.0.0.1:5588").await?;
+    second.send(b"2").await?;
+    assert!(
+        tokio::time::timeout(Duration::from_millis(100), second.recv(&mut buff))
+            .await
+            .is_err()
+    );
+    assert_eq!(udpserver.session_count().await, 1);
+
+    // 空闲超时后连接关闭,任务被中止,新地址可以连接
+    rx.recv().await.unwrap();
+    assert_eq!(udpserver.session_count().await, 0);
+    second.send(b"2").await?;
+    assert_eq!(second.recv(&mut buff).await?, 2);
+    Ok(())
+}
+
+#[tokio::test]
+async fn tcp_transport_peer() -> Result<()> {
+    let tcpserver = Builder::new("127.0.0.1:5570")
+        .set_stream_init(|tcp_stream| async move { Ok(tcp_stream) })
+        .set_input_event(|_, peer, _| async move {
+            greet(&*peer).await?;
+            ITransportPeer::disconnect(&*peer).await?;
+            Ok(())
+        })
+        .build()
+        .await;
+    tcpserver.start(()).await?;
+
+    let mut tcp_stream = tokio::net::TcpStream::connect("127.0.0.1:5570").await?;
+    let mut buff = String::new();
+    tcp_stream.read_to_string(&mut buff).await?;
+    assert_eq!(buff, format!("hello {}", tcp_stream.local_addr()?));
+    tcp_stream.shutdown().await?;
+    Ok(())
+}
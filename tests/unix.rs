@@ -0,0 +1,119 @@
+#![cfg(unix)]
+
+use anyhow::Result;
+use std::os::unix::fs::PermissionsExt;
+use tcpserver::{Bind, Builder, IPeer, ITCPServer, PauseMode, UnixBind};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+#[tokio::test]
+async fn unix_socket() -> Result<()> {
+    let path = std::env::temp_dir().join(format!("tcpserver_test_{}.sock", std::process::id()));
+    let tcpserver = Builder::new(UnixBind::new(&path).mode(0o600))
+        .set_connect_event(|addr| addr.unix_cred().is_some())
+        .set_stream_init(|stream| async move { Ok(stream) })
+        .set_input_event(|mut reader, peer, _| async move {
+            // 返回对端 uid 后回显
+            let cred = peer.addr().unix_cred().unwrap();
+            peer.send(cred.uid.to_le_bytes().to_vec()).await?;
+            let mut buff = [0; 16];
+            loop {
+                let len = reader.read(&mut buff).await?;
+                if len == 0 {
+                    break;
+                }
+                peer.send_ref(&buff[..len]).await?;
+            }
+            Ok(())
+        })
+        .build()
+        .await;
+    let meta = std::fs::metadata(&path)?;
+    assert_eq!(meta.permissions().mode() & 0o777, 0o600);
+    // 设置权限时先在临时目录中绑定再链接过来,不覆盖已存在的文件
+    let err = UnixBind::new(&path)
+        .mode(0o600)
+        .remove_existing(false)
+        .bind()
+        .await
+        .err()
+        .unwrap();
+    assert_eq!(err.kind(), std::io::ErrorKind::AddrInUse);
+    tcpserver.start(()).await?;
+
+    let mut stream = tokio::net::UnixStream::connect(&path).await?;
+    assert_eq!(stream.read_u32_le().await?, unsafe { libc::getuid() });
+    stream.write_all(b"hello").await?;
+    let mut buff = [0; 5];
+    stream.read_exact(&mut buff).await?;
+    assert_eq!(&buff, b"hello");
+    Ok(())
+}
+
+#[tokio::test]
+async fn unix_socket_cleanup() -> Result<()> {
+    let path = std::env::temp_dir().join(format!("tcpserver_clean_{}.sock", std::process::id()));
+    // 遗留的 socket 文件会被删除
+    drop(std::os::unix::net::UnixListener::bind(&path)?);
+    assert!(path.exists());
+    let tcpserver = Builder::new(UnixBind::new(&path))
+        .set_stream_init(|stream| async move { Ok(stream) })
+        .set_input_event(|_, _, _: ()| async move { Ok(()) })
+        .build()
+        .await;
+    assert!(path.exists());
+    drop(tcpserver);
+    assert!(!path.exists());
+    Ok(())
+}
+
+#[tokio::test]
+async fn unix_socket_reopen() -> Result<()> {
+    let path = std::env::temp_dir().join(format!("tcpserver_reopen_{}.sock", std::process::id()));
+    let tcpserver = Builder::new(UnixBind::new(&path).mode(0o600))
+        .set_stream_init(|stream| async move { Ok(stream) })
+        .set_input_event(|_, peer, _| async move {
+            peer.send_all(b"hi".to_vec()).await?;
+            Ok(())
+        })
+        .build()
+        .await;
+    tcpserver.start(()).await?;
+
+    // 关闭监听时清理 socket 文件,恢复后按原来的权限重新创建
+    tcpserver.pause_accept(PauseMode::CloseListener).await;
+    tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+    assert!(!path.exists());
+    tcpserver.resume_accept().await;
+    tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+    let meta = std::fs::metadata(&path)?;
+    assert_eq!(meta.permissions().mode() & 0o777, 0o600);
+    let mut stream = tokio::net::UnixStream::connect(&path).await?;
+    let mut buff = [0; 2];
+    stream.read_exact(&mut buff).await?;
+    assert_eq!(&buff, b"hi");
+    Ok(())
+}
+
+#[tokio::test]
+async fn unix_socket_reopen_keeps_existing() -> Result<()> {
+    let path = std::env::temp_dir().join(format!("tcpserver_keep_{}.sock", std::process::id()));
+    let tcpserver = Builder::new(UnixBind::new(&path).remove_existing(false))
+        .set_stream_init(|stream| async move { Ok(stream) })
+        .set_input_event(|_, _, _: ()| async move { Ok(()) })
+        .build()
+        .await;
+    let join = tcpserver.start(()).await?;
+    tcpserver.pause_accept(PauseMode::CloseListener).await;
+    tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+    assert!(!path.exists());
+
+    // 暂停期间其他进程占用了路径,恢复时不删除它的 socket 文件
+    let other = tokio::net::UnixListener::bind(&path)?;
+    tcpserver.resume_accept().await;
+    assert!(join.await?.is_err());
+    assert!(path.exists());
+    let _stream = tokio::net::UnixStream::connect(&path).await?;
+    other.accept().await?;
+    std::fs::remove_file(&path)?;
+    Ok(())
+}
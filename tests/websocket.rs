@@ -0,0 +1,40 @@
+#![cfg(feature = "websocket")]
+
+use anyhow::Result;
+use futures_util::{SinkExt, StreamExt};
+use tcpserver::{Builder, IPeer, ITCPServer};
+use tokio::io::AsyncReadExt;
+use tokio_tungstenite::tungstenite::Message;
+
+#[tokio::test]
+async fn websocket_echo() -> Result<()> {
+    let tcpserver = Builder::new("127.0.0.1:5571")
+        .set_stream_init(tcpserver::websocket::accept)
+        .set_input_event(|mut reader, peer, _| async move {
+            let mut buff = [0; 64];
+            loop {
+                let len = reader.read(&mut buff).await?;
+                if len == 0 || &buff[..len] == b"bye" {
+                    break;
+                }
+                peer.send_ref(&buff[..len]).await?;
+            }
+            Ok(())
+        })
+        .build()
+        .await;
+    tcpserver.start(()).await?;
+
+    let (mut ws, _) = tokio_tungstenite::connect_async("ws://127.0.0.1:5571").await?;
+    ws.send(Message::binary(&b"hello"[..])).await?;
+    assert_eq!(ws.next().await.unwrap()?, Message::binary(&b"hello"[..]));
+    ws.send(Message::text("text")).await?;
+    assert_eq!(ws.next().await.unwrap()?, Message::binary(&b"text"[..]));
+    ws.send(Message::Ping("ping".into())).await?;
+    assert_eq!(ws.next().await.unwrap()?, Message::Pong("ping".into()));
+
+    // 服务端断开时发送 close 帧
+    ws.send(Message::binary(&b"bye"[..])).await?;
+    assert!(matches!(ws.next().await.unwrap()?, Message::Close(_)));
+    Ok(())
+}